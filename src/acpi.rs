@@ -0,0 +1,189 @@
+use alloc::vec::Vec;
+use core::mem::size_of;
+use spin::Once;
+use x86_64::PhysAddr;
+use crate::memory::paging::phys_to_virt;
+
+/// The BIOS keeps the segment of the extended BIOS data area here
+const EBDA_SEGMENT_PTR: u64 = 0x40E;
+const EBDA_SEARCH_LENGTH: u64 = 1024;
+const BIOS_AREA_START: u64 = 0xE0000;
+const BIOS_AREA_END: u64 = 0x100000;
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+const MADT_SIGNATURE: &[u8; 4] = b"APIC";
+
+/// Bit 0 of the MADT flags, the machine also has the two 8259 PICs
+const PCAT_COMPAT: u32 = 1;
+/// Bit 0 of the processor entry flags
+const PROCESSOR_ENABLED: u32 = 1;
+
+// MADT entry types
+const ENTRY_PROCESSOR_LOCAL_APIC: u8 = 0;
+const ENTRY_IO_APIC: u8 = 1;
+const ENTRY_INTERRUPT_SOURCE_OVERRIDE: u8 = 2;
+const ENTRY_LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 5;
+
+/// Root System Description Pointer, the version 2 fields are only there when `revision` >= 2
+#[allow(dead_code)] // Laid out like the real thing, not every field is used
+#[repr(C, packed)]
+struct Rsdp {
+	signature: [u8; 8],
+	checksum: u8,
+	oem_id: [u8; 6],
+	revision: u8,
+	rsdt_address: u32,
+	// Version 2
+	length: u32,
+	xsdt_address: u64,
+	extended_checksum: u8,
+	reserved: [u8; 3],
+}
+
+/// Header every ACPI table starts with
+#[allow(dead_code)]
+#[repr(C, packed)]
+struct SdtHeader {
+	signature: [u8; 4],
+	length: u32,
+	revision: u8,
+	checksum: u8,
+	oem_id: [u8; 6],
+	oem_table_id: [u8; 8],
+	oem_revision: u32,
+	creator_id: u32,
+	creator_revision: u32,
+}
+
+/// An IO APIC, which takes the interrupts starting at `gsi_base`
+#[derive(Debug, Clone, Copy)]
+pub struct IoApicEntry {
+	pub address: PhysAddr,
+	pub gsi_base: u32,
+}
+
+/// An ISA IRQ that isn't wired to the IO APIC input of the same number, or not with the ISA defaults
+/// (edge triggered, active high)
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptOverride {
+	pub irq: u8,
+	pub gsi: u32,
+	/// Polarity in bits 0-1 and trigger mode in bits 2-3, 0 meaning the bus default
+	pub flags: u16,
+}
+
+/// What the MADT (Multiple APIC Description Table) says about the interrupt controllers and CPUs
+#[derive(Debug)]
+pub struct Madt {
+	pub local_apic_address: PhysAddr,
+	/// Local APIC IDs of the usable CPUs, the boot CPU included
+	pub processor_apic_ids: Vec<u8>,
+	pub io_apics: Vec<IoApicEntry>,
+	pub overrides: Vec<InterruptOverride>,
+	pub has_8259_pics: bool,
+}
+
+static MADT: Once<Option<Madt>> = Once::new();
+
+/// The MADT, found and parsed the first time this is called. None if there is no ACPI, or no MADT in it.
+pub fn madt() -> Option<&'static Madt> {
+	MADT.call_once(|| unsafe { find_rsdp().and_then(find_madt).map(|header| parse_madt(header)) })
+		.as_ref()
+}
+
+unsafe fn read<T>(addr: PhysAddr) -> T {
+	core::ptr::read_unaligned(phys_to_virt(addr).as_ptr::<T>())
+}
+
+/// All the bytes of a table add up to 0
+unsafe fn checksum_ok(addr: PhysAddr, length: usize) -> bool {
+	let bytes = core::slice::from_raw_parts(phys_to_virt(addr).as_ptr::<u8>(), length);
+	bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
+
+/// The RSDP is on a 16 byte boundary in the first KiB of the EBDA, or in the BIOS area below 1MiB
+unsafe fn find_rsdp() -> Option<PhysAddr> {
+	let ebda_start = (read::<u16>(PhysAddr::new(EBDA_SEGMENT_PTR)) as u64) << 4;
+	let search = |start: u64, end: u64| (start..end).step_by(16)
+		.map(PhysAddr::new)
+		.find(|&addr| read::<[u8; 8]>(addr) == *RSDP_SIGNATURE && checksum_ok(addr, 20));
+	
+	if ebda_start != 0 {
+		if let Some(rsdp) = search(ebda_start, ebda_start + EBDA_SEARCH_LENGTH) {
+			return Some(rsdp);
+		}
+	}
+	search(BIOS_AREA_START, BIOS_AREA_END)
+}
+
+/// Go through the RSDT (or the XSDT from ACPI 2.0 on) for the MADT
+unsafe fn find_madt(rsdp_addr: PhysAddr) -> Option<PhysAddr> {
+	let rsdp = read::<Rsdp>(rsdp_addr);
+	let (root, entry_size) = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+		(PhysAddr::new(rsdp.xsdt_address), size_of::<u64>())
+	} else {
+		(PhysAddr::new(rsdp.rsdt_address as u64), size_of::<u32>())
+	};
+	let root_header = read::<SdtHeader>(root);
+	if !checksum_ok(root, root_header.length as usize) {
+		return None;
+	}
+	
+	let entry_count = (root_header.length as usize - size_of::<SdtHeader>()) / entry_size;
+	(0..entry_count)
+		.map(|i| root + (size_of::<SdtHeader>() + i * entry_size) as u64)
+		.map(|entry| if entry_size == size_of::<u64>() {
+			PhysAddr::new(read::<u64>(entry))
+		} else {
+			PhysAddr::new(read::<u32>(entry) as u64)
+		})
+		.find(|&table| {
+			let header = read::<SdtHeader>(table);
+			header.signature == *MADT_SIGNATURE && checksum_ok(table, header.length as usize)
+		})
+}
+
+/// After the header comes the local APIC address and the flags, then the variable length entries
+unsafe fn parse_madt(madt_addr: PhysAddr) -> Madt {
+	let header = read::<SdtHeader>(madt_addr);
+	let fields = madt_addr + size_of::<SdtHeader>() as u64;
+	let mut madt = Madt {
+		local_apic_address: PhysAddr::new(read::<u32>(fields) as u64),
+		processor_apic_ids: Vec::new(),
+		io_apics: Vec::new(),
+		overrides: Vec::new(),
+		has_8259_pics: read::<u32>(fields + 4u64) & PCAT_COMPAT != 0,
+	};
+	
+	let end = madt_addr + header.length as u64;
+	let mut entry = fields + 8u64;
+	while entry + 2u64 <= end {
+		let entry_type = read::<u8>(entry);
+		let length = read::<u8>(entry + 1u64);
+		if length < 2 {
+			break; // Broken table, don't loop forever
+		}
+		match entry_type {
+			ENTRY_PROCESSOR_LOCAL_APIC => {
+				let apic_id = read::<u8>(entry + 3u64);
+				if read::<u32>(entry + 4u64) & PROCESSOR_ENABLED != 0 {
+					madt.processor_apic_ids.push(apic_id);
+				}
+			}
+			ENTRY_IO_APIC => madt.io_apics.push(IoApicEntry {
+				address: PhysAddr::new(read::<u32>(entry + 4u64) as u64),
+				gsi_base: read::<u32>(entry + 8u64),
+			}),
+			ENTRY_INTERRUPT_SOURCE_OVERRIDE => madt.overrides.push(InterruptOverride {
+				irq: read::<u8>(entry + 3u64),
+				gsi: read::<u32>(entry + 4u64),
+				flags: read::<u16>(entry + 8u64),
+			}),
+			ENTRY_LOCAL_APIC_ADDRESS_OVERRIDE => {
+				madt.local_apic_address = PhysAddr::new(read::<u64>(entry + 4u64));
+			}
+			_ => {} // x2APIC and NMI entries, nothing uses those yet
+		}
+		entry += length as u64;
+	}
+	madt
+}
\ No newline at end of file
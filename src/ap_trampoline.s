@@ -0,0 +1,87 @@
+.intel_syntax noprefix
+
+//; Application processors start here in real mode after the startup IPI, at the start of a page below 1MiB.
+//; This is copied to that page by smp_init, so anything that depends on where it ended up
+//; (the GDT and far jump addresses, plus ap_trampoline_args) is filled in before each AP is started.
+//; Goes straight from real mode to long mode, using the same page tables as the bootstrap CPU.
+
+.global ap_trampoline_start
+.global ap_trampoline_end
+.global ap_long_mode
+.global ap_gdt
+.global ap_gdt_pointer
+.global ap_far_pointer
+.global ap_trampoline_args
+
+.code16
+ap_trampoline_start:
+	cli
+	cld
+	mov ax, cs
+	mov ds, ax // Everything below is addressed relative to the start of the page
+
+	lgdt [ap_gdt_pointer - ap_trampoline_start]
+
+	mov eax, cr4
+	or eax, 0xA0 // PAE and PGE
+	mov cr4, eax
+
+	mov eax, [ap_args_cr3 - ap_trampoline_start] // The page tables have to be below 4GiB
+	mov cr3, eax
+
+	mov ecx, 0xC0000080 // EFER
+	rdmsr
+	or eax, 0x900 // Long mode enable, and no-execute enable since the page tables use it
+	wrmsr
+
+	mov eax, cr0
+	and eax, 0x9FFFFFFF // Caching is off after INIT, turn it back on
+	or eax, 0x80000001 // Paging and protection at the same time, straight into compatibility mode
+	mov cr0, eax
+
+	jmp fword ptr [ap_far_pointer - ap_trampoline_start] // Load the 64 bit code segment
+
+.code64
+ap_long_mode:
+	xor ax, ax
+	mov ds, ax
+	mov es, ax
+	mov ss, ax
+	mov fs, ax
+	mov gs, ax
+
+	mov rsp, [rip + ap_args_stack_top]
+	mov rdi, [rip + ap_args_cpu_index] //; ap_main(cpu_index, stack_top)
+	mov rsi, rsp
+	mov rax, [rip + ap_args_entry]
+	call rax // Jump up to the kernel, ap_main never returns
+
+ap_halt:
+	hlt
+	jmp ap_halt
+
+.align 16
+ap_gdt:
+	.quad 0
+	.quad 0x00AF9A000000FFFF // 64 bit code
+	.quad 0x00CF92000000FFFF // Data
+ap_gdt_pointer:
+	.word 23
+	.long 0 // Address of ap_gdt
+
+.align 8
+ap_far_pointer:
+	.long 0 // Address of ap_long_mode
+	.word 0x08
+
+.align 8
+ap_trampoline_args: //; Laid out like TrampolineArgs
+ap_args_cr3:
+	.quad 0
+ap_args_stack_top:
+	.quad 0
+ap_args_entry:
+	.quad 0
+ap_args_cpu_index:
+	.quad 0
+ap_trampoline_end:
\ No newline at end of file
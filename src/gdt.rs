@@ -3,6 +3,7 @@ use x86_64::VirtAddr;
 use x86_64::structures::{tss::TaskStateSegment, gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector}};
 use lazy_static::lazy_static;
 use core::ops::Deref;
+use alloc::boxed::Box;
 
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
 const DF_STACK_SIZE: usize = 4096;
@@ -27,6 +28,31 @@ pub fn gdt_init() {
 	}
 }
 
+/// Load a new GDT and TSS on an application processor, with `kernel_stack_top` as the stack for interrupts and syscalls.
+/// Each CPU needs its own TSS, since it holds the kernel stack and the syscall scratch space.
+pub fn gdt_init_ap(kernel_stack_top: VirtAddr) -> &'static TaskStateSegment {
+	let mut tss = TaskStateSegment::new();
+	tss.privilege_stack_table[0] = kernel_stack_top;
+	let df_stack = crate::memory::alloc_stack((DF_STACK_SIZE / 4096) as u64,
+											  &mut *crate::TEMP_MAPPER.lock().as_mut().unwrap(),
+											  &mut *crate::FRAME_ALLOCATOR.lock())
+		.expect("Failed to create double fault stack");
+	tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = df_stack.end();
+	let tss: &'static TaskStateSegment = Box::leak(Box::new(tss));
+	
+	// Same layout as the boot CPU's GDT, so the selectors in `GDT` work everywhere
+	let mut gdt = GlobalDescriptorTable::new();
+	let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
+	let tss_selector = gdt.add_entry(Descriptor::tss_segment(tss));
+	let gdt: &'static GlobalDescriptorTable = Box::leak(Box::new(gdt));
+	gdt.load();
+	unsafe {
+		x86_64::instructions::segmentation::set_cs(code_selector);
+		x86_64::instructions::tables::load_tss(tss_selector);
+	}
+	tss
+}
+
 fn create_task_state_segment() -> TaskStateSegment {
 	let mut tss = TaskStateSegment::new();
 	
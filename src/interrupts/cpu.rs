@@ -35,6 +35,11 @@ pub extern "x86-interrupt" fn page_fault_handler(
 ) {
 	use x86_64::registers::control::Cr2;
 	
+	// A bad pointer from a process, the copy returns an error instead
+	if crate::memory::user::fixup_fault(stack_frame) {
+		return;
+	}
+	
 	println!("EXCEPTION: PAGE FAULT");
 	println!("Accessed Address: {:?}", Cr2::read());
 	println!("Error Code: {:?}", error_code);
@@ -1,8 +1,10 @@
 use pic8259_simple::ChainedPics;
 use x86_64::structures::idt::InterruptStackFrame;
 use lazy_static::lazy_static;
+use core::sync::atomic::{AtomicBool, Ordering};
 use crate::print;
 use crate::processes::PROCESS_MANAGER;
+use crate::smp::BOOT_CPU;
 
 pub const PIC_1_OFFSET: u8 = 32;
 pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
@@ -10,11 +12,56 @@ pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 pub static PICS: spin::Mutex<ChainedPics> = // Remap PIC ports via offset
 	spin::Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
 
+/// Set once the local APIC and IO APIC have taken over from the PICs
+static APIC_MODE: AtomicBool = AtomicBool::new(false);
+
+/// The ISA IRQs keep the vectors the PIC would give them (`PIC_1_OFFSET` + IRQ), whichever controller sends them
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum InterruptIndex {
+	/// The PIT, only used when there is no APIC
 	Timer = PIC_1_OFFSET,
 	Keyboard, // +1
+	/// COM1
+	Serial = PIC_1_OFFSET + 4,
+	/// Local APIC timer of every CPU, past the PIC vectors
+	ApicTimer = PIC_2_OFFSET + 8,
+}
+
+impl InterruptIndex {
+	/// IRQ line on the PIC, or ISA IRQ for the IO APIC
+	pub fn isa_irq(self) -> u8 {
+		self as u8 - PIC_1_OFFSET
+	}
+}
+
+pub fn apic_mode() -> bool {
+	APIC_MODE.load(Ordering::Relaxed)
+}
+
+pub fn set_apic_mode() {
+	APIC_MODE.store(true, Ordering::Relaxed);
+}
+
+/// Mask every line on both PICs, so they can't get in the way of the IO APIC.
+/// They still have to be remapped first, since they can send spurious interrupts even when masked.
+pub fn disable_pics() {
+	use x86_64::instructions::port::Port;
+	const PIC_1_DATA: u16 = 0x21;
+	const PIC_2_DATA: u16 = 0xA1;
+	unsafe {
+		Port::<u8>::new(PIC_1_DATA).write(0xFF);
+		Port::<u8>::new(PIC_2_DATA).write(0xFF);
+	}
+}
+
+/// Tell whichever controller sent `index` that it's been handled
+pub fn end_of_interrupt(index: InterruptIndex) {
+	if apic_mode() {
+		super::lapic::end_of_interrupt();
+	} else {
+		unsafe { PICS.lock().notify_end_of_interrupt(index as u8) }
+	}
 }
 // _stack_frame: &mut InterruptStackFrame
 
@@ -65,15 +112,28 @@ pub extern "x86-interrupt" fn timer_interrupt_handler() {
 unsafe extern "C" fn timer_internal(stack_p: usize) -> usize {
 	
 	// println!("YAY");
-	let new_stack_pointer = PROCESS_MANAGER.try_lock()
+	// The boot CPU keeps time, so it can't skip a tick. It waits for the lock instead, which is only ever held with
+	// interrupts off, so never by the boot CPU itself at this point. The other CPUs just skip the tick.
+	let p_manager = if crate::smp::cpu_index() == BOOT_CPU {
+		crate::time::count_tick();
+		Some(PROCESS_MANAGER.lock())
+	} else {
+		PROCESS_MANAGER.try_lock()
+	};
+	let new_stack_pointer = p_manager
 		.and_then(|mut p_manager| p_manager.next_tick_preempt_process(stack_p))
 		.map(|c| c.as_u64() as usize)
 		.unwrap_or(stack_p);
 	
-	PICS.lock().notify_end_of_interrupt(InterruptIndex::Timer as u8);
+	// Either the PIT through the PIC, or the local APIC timer (of any CPU)
+	end_of_interrupt(InterruptIndex::Timer);
 	
 	new_stack_pointer
 }
+
+/// The local APIC dropped an interrupt, nothing to do (not even an EOI)
+pub extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: &mut InterruptStackFrame) {}
+
 // #[inline(never)]
 // pub extern "C" fn random_thing(_stack_frame: usize) {
 // 	let stack_pointer: usize;
@@ -118,7 +178,25 @@ pub extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: &mut Inte
 		}
 	}
 	
-	unsafe {
-		PICS.lock().notify_end_of_interrupt(InterruptIndex::Keyboard as u8);
+	end_of_interrupt(InterruptIndex::Keyboard);
+}
+
+/// A byte came in on COM1, echo it like a key press
+pub extern "x86-interrupt" fn serial_interrupt_handler(_stack_frame: &mut InterruptStackFrame) {
+	use x86_64::instructions::port::Port;
+	const COM1_DATA: u16 = 0x3F8;
+	const COM1_LINE_STATUS: u16 = 0x3FD;
+	const DATA_READY: u8 = 1;
+	
+	let mut data: Port<u8> = Port::new(COM1_DATA);
+	let mut line_status: Port<u8> = Port::new(COM1_LINE_STATUS);
+	// Empty the receive buffer, otherwise the UART doesn't interrupt again
+	while unsafe { line_status.read() } & DATA_READY != 0 {
+		match unsafe { data.read() } {
+			b'\r' => print!("\n"),
+			byte => print!("{}", byte as char),
+		}
 	}
+	
+	end_of_interrupt(InterruptIndex::Serial);
 }
\ No newline at end of file
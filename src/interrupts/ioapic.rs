@@ -0,0 +1,99 @@
+use alloc::vec::Vec;
+use spin::Mutex;
+use x86_64::VirtAddr;
+use crate::acpi::{Madt, IoApicEntry};
+
+// Registers are reached by writing their index to IOREGSEL, then using IOWIN
+const IOREGSEL: usize = 0x00;
+const IOWIN: usize = 0x10;
+
+const VERSION: u32 = 0x01;
+/// Each redirection entry is 2 registers, the low half then the high half
+const REDIRECTION_TABLE: u32 = 0x10;
+
+const ENTRY_ACTIVE_LOW: u32 = 1 << 13;
+const ENTRY_LEVEL_TRIGGERED: u32 = 1 << 15;
+const ENTRY_MASKED: u32 = 1 << 16;
+
+// Interrupt source override flags
+const POLARITY_MASK: u16 = 0b11;
+const POLARITY_ACTIVE_LOW: u16 = 0b11;
+const TRIGGER_MASK: u16 = 0b11 << 2;
+const TRIGGER_LEVEL: u16 = 0b11 << 2;
+
+struct IoApic {
+	base: VirtAddr,
+	gsi_base: u32,
+	entry_count: u32,
+}
+
+impl IoApic {
+	fn read(&self, register: u32) -> u32 {
+		unsafe {
+			core::ptr::write_volatile((self.base + IOREGSEL).as_mut_ptr::<u32>(), register);
+			core::ptr::read_volatile((self.base + IOWIN).as_ptr::<u32>())
+		}
+	}
+	
+	fn write(&self, register: u32, value: u32) {
+		unsafe {
+			core::ptr::write_volatile((self.base + IOREGSEL).as_mut_ptr::<u32>(), register);
+			core::ptr::write_volatile((self.base + IOWIN).as_mut_ptr::<u32>(), value);
+		}
+	}
+	
+	fn handles(&self, gsi: u32) -> bool {
+		gsi >= self.gsi_base && gsi < self.gsi_base + self.entry_count
+	}
+	
+	fn set_entry(&self, gsi: u32, low: u32, high: u32) {
+		let register = REDIRECTION_TABLE + (gsi - self.gsi_base) * 2;
+		// Masked while it's half written
+		self.write(register, ENTRY_MASKED);
+		self.write(register + 1, high);
+		self.write(register, low);
+	}
+}
+
+/// Locked since IOREGSEL and IOWIN have to be used as a pair
+static IO_APICS: Mutex<Vec<IoApic>> = Mutex::new(Vec::new());
+
+/// Map every IO APIC in the MADT, with all of their inputs masked
+pub fn ioapic_init(madt: &Madt) {
+	let mut io_apics = IO_APICS.lock();
+	for &IoApicEntry { address, gsi_base, .. } in &madt.io_apics {
+		let base = crate::memory::map_mmio(address,
+										   &mut *crate::TEMP_MAPPER.lock().as_mut().unwrap(),
+										   &mut *crate::FRAME_ALLOCATOR.lock())
+			.expect("Failed to map an IO APIC");
+		let mut io_apic = IoApic { base, gsi_base, entry_count: 0 };
+		io_apic.entry_count = ((io_apic.read(VERSION) >> 16) & 0xFF) + 1;
+		for gsi in gsi_base..gsi_base + io_apic.entry_count {
+			io_apic.set_entry(gsi, ENTRY_MASKED, 0);
+		}
+		io_apics.push(io_apic);
+	}
+}
+
+/// Send ISA `irq` (what it would be on the PIC) to `vector` on the CPU with `apic_id`, going through
+/// the MADT's interrupt source overrides for which IO APIC input it's on, and how it's triggered.
+/// Returns Err if no IO APIC has that input.
+pub fn route_isa_irq(madt: &Madt, irq: u8, vector: u8, apic_id: u8) -> Result<(), ()> {
+	let (gsi, flags) = madt.overrides.iter()
+		.find(|o| o.irq == irq)
+		.map(|o| (o.gsi, o.flags))
+		.unwrap_or((irq as u32, 0)); // ISA defaults, edge triggered and active high
+	
+	let mut low = vector as u32; // Fixed delivery, to a physical APIC ID
+	if flags & POLARITY_MASK == POLARITY_ACTIVE_LOW {
+		low |= ENTRY_ACTIVE_LOW;
+	}
+	if flags & TRIGGER_MASK == TRIGGER_LEVEL {
+		low |= ENTRY_LEVEL_TRIGGERED;
+	}
+	
+	let io_apics = IO_APICS.lock();
+	let io_apic = io_apics.iter().find(|io_apic| io_apic.handles(gsi)).ok_or(())?;
+	io_apic.set_entry(gsi, low, (apic_id as u32) << 24);
+	Ok(())
+}
\ No newline at end of file
@@ -0,0 +1,115 @@
+use core::sync::atomic::{AtomicU64, AtomicU32, Ordering};
+use x86_64::{PhysAddr, VirtAddr};
+
+// Register offsets from the APIC base
+const ID: usize = 0x20;
+const EOI: usize = 0xB0;
+const SPURIOUS_VECTOR: usize = 0xF0;
+const ICR_LOW: usize = 0x300;
+const ICR_HIGH: usize = 0x310;
+const LVT_TIMER: usize = 0x320;
+const TIMER_INITIAL_COUNT: usize = 0x380;
+const TIMER_CURRENT_COUNT: usize = 0x390;
+const TIMER_DIVIDE: usize = 0x3E0;
+
+const SOFTWARE_ENABLE: u32 = 1 << 8;
+const ICR_DELIVERY_PENDING: u32 = 1 << 12;
+const ICR_INIT: u32 = 0b101 << 8;
+const ICR_STARTUP: u32 = 0b110 << 8;
+const ICR_LEVEL_ASSERT: u32 = 1 << 14;
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+/// Divide the bus clock by 16
+const TIMER_DIVIDE_BY_16: u32 = 0b0011;
+
+/// Vector for interrupts the APIC had to drop, they don't get an EOI
+pub const SPURIOUS_INTERRUPT_VECTOR: u8 = 0xFF;
+
+/// Virtual address of the registers, 0 until `lapic_init`
+static LAPIC_BASE: AtomicU64 = AtomicU64::new(0);
+/// Initial count that makes the timer fire once per scheduler tick, 0 until it's calibrated
+static TIMER_COUNTS_PER_TICK: AtomicU32 = AtomicU32::new(0);
+
+/// Map the local APIC registers at `phys_base` (from the MADT), they are at the same physical address on every CPU
+/// (each CPU only sees its own APIC there).
+pub fn lapic_init(phys_base: PhysAddr) {
+	let virt_base = crate::memory::map_mmio(phys_base,
+											&mut *crate::TEMP_MAPPER.lock().as_mut().unwrap(),
+											&mut *crate::FRAME_ALLOCATOR.lock())
+		.expect("Failed to map the local APIC");
+	LAPIC_BASE.store(virt_base.as_u64(), Ordering::Relaxed);
+}
+
+/// CPUID says whether there is a local APIC at all, really old machines (and `-cpu` options) don't have one
+pub fn is_present() -> bool {
+	unsafe { core::arch::x86_64::__cpuid(1) }.edx & (1 << 9) != 0
+}
+
+pub fn is_mapped() -> bool {
+	LAPIC_BASE.load(Ordering::Relaxed) != 0
+}
+
+fn read(register: usize) -> u32 {
+	let base = VirtAddr::new(LAPIC_BASE.load(Ordering::Relaxed));
+	unsafe { core::ptr::read_volatile((base + register).as_ptr::<u32>()) }
+}
+
+fn write(register: usize, value: u32) {
+	let base = VirtAddr::new(LAPIC_BASE.load(Ordering::Relaxed));
+	unsafe { core::ptr::write_volatile((base + register).as_mut_ptr::<u32>(), value) }
+}
+
+/// Turn on the APIC of the CPU this runs on
+pub fn enable() {
+	write(SPURIOUS_VECTOR, SOFTWARE_ENABLE | SPURIOUS_INTERRUPT_VECTOR as u32);
+}
+
+/// APIC ID of the CPU this runs on
+pub fn id() -> u8 {
+	(read(ID) >> 24) as u8
+}
+
+pub fn end_of_interrupt() {
+	write(EOI, 0);
+}
+
+fn send_ipi(apic_id: u8, command: u32) {
+	write(ICR_HIGH, (apic_id as u32) << 24);
+	write(ICR_LOW, command); // Writing the low half is what sends it
+	while read(ICR_LOW) & ICR_DELIVERY_PENDING != 0 {}
+}
+
+/// Reset the CPU with `apic_id`, it then waits for a startup IPI
+pub fn send_init(apic_id: u8) {
+	send_ipi(apic_id, ICR_INIT | ICR_LEVEL_ASSERT);
+}
+
+/// Start the CPU with `apic_id` in real mode at `start_page` * 4096, which has to be below 1MiB
+pub fn send_startup(apic_id: u8, start_page: u8) {
+	send_ipi(apic_id, ICR_STARTUP | ICR_LEVEL_ASSERT | start_page as u32);
+}
+
+/// Count how fast the APIC timer runs against the TSC, so `start_timer` can fire at the scheduler tick rate.
+/// Only has to be done once, the timers of all the CPUs run off the same bus clock.
+pub fn calibrate_timer() {
+	const CALIBRATION_NS: u64 = 10_000_000;
+	write(TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+	write(LVT_TIMER, LVT_MASKED);
+	write(TIMER_INITIAL_COUNT, u32::MAX);
+	let start = crate::time::time_ns();
+	while crate::time::time_ns() - start < CALIBRATION_NS {}
+	let counted = u32::MAX - read(TIMER_CURRENT_COUNT);
+	write(TIMER_INITIAL_COUNT, 0);
+	
+	let counts_per_tick = counted as u64 * crate::time::TICK_LENGTH_NS / CALIBRATION_NS;
+	TIMER_COUNTS_PER_TICK.store(counts_per_tick as u32, Ordering::Relaxed);
+}
+
+/// Fire `vector` on this CPU once per scheduler tick
+pub fn start_timer(vector: u8) {
+	let counts_per_tick = TIMER_COUNTS_PER_TICK.load(Ordering::Relaxed);
+	assert_ne!(counts_per_tick, 0, "APIC timer used before it was calibrated");
+	write(TIMER_DIVIDE, TIMER_DIVIDE_BY_16);
+	write(LVT_TIMER, LVT_TIMER_PERIODIC | vector as u32);
+	write(TIMER_INITIAL_COUNT, counts_per_tick);
+}
\ No newline at end of file
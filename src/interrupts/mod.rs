@@ -4,32 +4,69 @@ mod helper_macros;
 use x86_64::structures::idt::InterruptDescriptorTable;
 use hardware::InterruptIndex;
 use lazy_static::lazy_static;
-use num_enum::TryFromPrimitive;
-use core::convert::TryFrom;
-use x86_64::VirtAddr;
-use crate::println;
+use crate::eprintln;
+use crate::acpi::Madt;
+use core::sync::atomic::AtomicBool;
 
 mod cpu;
 pub mod hardware;
-
-#[derive(Debug, Copy, Clone, TryFromPrimitive)]
-#[repr(u64)]
-pub enum SyscallCommand {
-	Yield = 10,
-	Terminate,
-	/// Temporary solution to stop the testers.
-	TerminateEverythingElse,
-}
+pub mod pit;
+pub mod lapic;
+pub mod ioapic;
+pub mod syscall;
+pub mod strace;
 
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = create_idt();
 }
 
+/// Load the IDT and set up the interrupt controllers, the APICs if the MADT lists them, the PICs otherwise.
+/// Needs the TSC to be calibrated, for the local APIC timer.
 pub fn interrupt_init() {
-	IDT.load();
+	idt_init();
+	// Remapped even if they aren't used, so anything they send doesn't look like a CPU exception
 	unsafe { hardware::PICS.lock().initialize() }
+	match crate::acpi::madt() {
+		Some(madt) if lapic::is_present() && !madt.io_apics.is_empty() => apic_init(madt),
+		_ => {
+			eprintln!("WARNING: No APIC found, falling back to the PIC, and only using the boot CPU");
+			pit::set_channel_0_divisor(crate::time::PIT_DIVISOR);
+		}
+	}
+}
+
+/// Mask the PICs and move everything onto the APICs: ticks come from the local APIC timer,
+/// and the keyboard and serial IRQs go through the IO APIC to the boot CPU.
+fn apic_init(madt: &Madt) {
+	if madt.has_8259_pics {
+		hardware::disable_pics();
+	}
+	
+	lapic::lapic_init(madt.local_apic_address);
+	lapic::enable();
+	lapic::calibrate_timer();
+	lapic::start_timer(InterruptIndex::ApicTimer as u8);
+	
+	ioapic::ioapic_init(madt);
+	let boot_apic_id = lapic::id();
+	for &index in &[InterruptIndex::Keyboard, InterruptIndex::Serial] {
+		if ioapic::route_isa_irq(madt, index.isa_irq(), index as u8, boot_apic_id).is_err() {
+			eprintln!("WARNING: No IO APIC input for IRQ {}", index.isa_irq());
+		}
+	}
+	hardware::set_apic_mode();
+}
+
+/// Every CPU shares the same IDT, but has to load it itself
+pub fn idt_init() {
+	IDT.load();
 }
 
+/// Whether `syscall_handler` returns straight to the caller when it didn't switch processes, instead of going
+/// through `iretq`. Only there so the benchmark can compare the two, it's read by name from the handler.
+#[no_mangle]
+pub static SYSCALL_FAST_RETURN: AtomicBool = AtomicBool::new(true);
+
 // TODO: Actually have a kernel stack pointer
 // The user calling this syscall better have interrupt disabled, how are they going to do that in usermode?
 // who knows, good thing everyone is in kernel mode I suppose.
@@ -56,7 +93,8 @@ pub unsafe extern fn syscall_handler() -> ! {
 	interrupt_push!();
 	
 	llvm_asm!("
-		mov rdi, rsp // Store process rsp as first argument
+		mov rdi, rsp // Store process rsp as first argument, the syscall number and arguments are read from there
+		mov rbx, rsp // Kept through the call (rbx is already saved), to see if we come back to the same process
 		mov rsp, qword ptr gs:[4] // Get the ring 0 stack pointer
 		swapgs // Move gs back to TSS
 	"
@@ -67,65 +105,45 @@ pub unsafe extern fn syscall_handler() -> ! {
 	
 	// TODO: Make everything use the same syntax, basically I haven't found a example for how to do rust function calls with the intel syntax lol
 	llvm_asm!( "
-			mov %rax, %rsi //; Pass rax (first argument of syscall) as second argument
 			call ${0:c}
 			// I don't think we need to save the kernel stack pointer...
 			mov %rax, %rsp // Use return number as stack pointer
-			": : "i"(internal_syscall as u64) : "memory", "rsp", "rdi", "rsi", "rax" : "volatile", "alignstack");
+			": : "i"(syscall::internal_syscall as u64) : "memory", "rsp", "rdi", "rsi", "rdx", "rax", "rbx" : "volatile", "alignstack");
+	
+	// Switched to another process, its registers might have been saved by an interrupt, so only iretq can restore them
+	llvm_asm!("
+		cmp rax, rbx
+		jne syscall_iret_return
+		cmp byte ptr [rip + SYSCALL_FAST_RETURN], 0
+		je syscall_iret_return
+		"
+		:
+		:
+		:
+		: "intel", "volatile");
 	
+	// Fast path, back to the caller. This does what sysretq does (rip from rcx, rflags from r11), since the syscall
+	// instruction left those in rcx and r11 and `interrupt_pop!` restores them. sysretq itself always returns to
+	// ring 3, and the processes still run in ring 0.
+	interrupt_pop!();
+	llvm_asm!("
+		mov rsp, qword ptr [rsp + 24] // The caller's rsp, from the frame pushed on the way in
+		push r11 // No red zone, so it's fine to use the caller's stack
+		popfq
+		jmp rcx
+		"
+		:
+		:
+		:
+		: "intel", "volatile");
+	
+	llvm_asm!("syscall_iret_return:" : : : : "intel", "volatile");
 	interrupt_pop!();
 	// TODO: There is a lot of things wrong here, we are assuming everything is just in kernel space.
 	llvm_asm!("iretq");
 	unreachable!();
 }
 
-#[inline(never)]
-extern "C" fn internal_syscall(stack_p: usize, call_num: usize) -> usize {
-	use crate::processes::PROCESS_MANAGER;
-	
-	let call_num = SyscallCommand::try_from(call_num as u64)
-		.expect("Invalid Syscall Number");
-	
-	match call_num {
-		SyscallCommand::Yield => {
-			PROCESS_MANAGER.try_lock().expect("Disabled interrupts here, need to deal with locked PM")
-				.yield_current_process(VirtAddr::new(stack_p as u64)).as_u64() as usize
-		},
-		SyscallCommand::Terminate => {
-			PROCESS_MANAGER.try_lock().expect("Disabled interrupts here, need to deal with locked PM")
-				.end_current_process().as_u64() as usize
-		}
-		SyscallCommand::TerminateEverythingElse => {
-			PROCESS_MANAGER.try_lock().expect("Disabled interrupts here, need to deal with locked PM")
-				.end_all_other_processes();
-			stack_p
-		}
-	}
-}
-
-// Reference: https://en.wikibooks.org/wiki/X86_Assembly/Interfacing_with_Linux
-
-#[inline(always)]
-pub fn syscall1(call_num: SyscallCommand) -> u64 {
-	let ret: u64;
-	unsafe {
-		llvm_asm!("syscall" : "={rax}" (ret) : "{rax}" (call_num as u64) :
-			"rcx", "r11", "memory" : "volatile");
-	}
-	ret
-}
-
-#[inline(always)]
-pub fn syscall2(call_num: SyscallCommand, arg1: u64) -> u64 {
-	let ret: u64;
-	unsafe {
-		llvm_asm!("syscall" : "={rax}" (ret) : "{rax}" (call_num as u64), "{rdi}" (arg1) :
-			"rcx", "r11", "memory" : "volatile");
-	}
-	ret
-}
-
-
 fn create_idt() -> InterruptDescriptorTable {
 	let mut idt = InterruptDescriptorTable::new();
 	idt.breakpoint.set_handler_fn(cpu::breakpoint_handler);
@@ -150,7 +168,14 @@ fn create_idt() -> InterruptDescriptorTable {
 	// But we have a custom naked function, so we just pretend what we passed is correct by transmuting it.
 	idt[InterruptIndex::Timer as u8 as usize]
 		.set_handler_fn(unsafe { core::mem::transmute(timer_interrupt_address) });
+	// Same handler whichever timer the ticks come from
+	idt[InterruptIndex::ApicTimer as u8 as usize]
+		.set_handler_fn(unsafe { core::mem::transmute(timer_interrupt_address) });
+	idt[lapic::SPURIOUS_INTERRUPT_VECTOR as usize]
+		.set_handler_fn(hardware::spurious_interrupt_handler);
 	idt[InterruptIndex::Keyboard as u8 as usize]
 		.set_handler_fn(hardware::keyboard_interrupt_handler);
+	idt[InterruptIndex::Serial as u8 as usize]
+		.set_handler_fn(hardware::serial_interrupt_handler);
 	idt
 }
\ No newline at end of file
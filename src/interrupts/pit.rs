@@ -0,0 +1,70 @@
+use x86_64::instructions::port::Port;
+
+/// Frequency of the oscillator driving the PIT, every channel counts down at this rate
+pub const PIT_BASE_FREQUENCY_HZ: u64 = 1_193_182;
+
+const CHANNEL_0_DATA_PORT: u16 = 0x40;
+const COMMAND_PORT: u16 = 0x43;
+/// Channel 0, low byte then high byte, mode 2 (rate generator), binary counting
+const CHANNEL_0_RATE_GENERATOR: u8 = 0b0011_0100;
+
+/// Divisor that gets channel 0 closest to `frequency_hz`, clamped to what the PIT can do.
+/// 0 is how the PIT spells 65536, the slowest it can go.
+pub const fn divisor_for(frequency_hz: u64) -> u16 {
+	let divisor = (PIT_BASE_FREQUENCY_HZ + frequency_hz / 2) / frequency_hz;
+	if divisor >= 0x10000 {
+		0
+	} else if divisor == 0 {
+		1
+	} else {
+		divisor as u16
+	}
+}
+
+/// Make channel 0 (hooked up to IRQ 0) fire every `divisor` counts of the base frequency
+pub fn set_channel_0_divisor(divisor: u16) {
+	let mut command: Port<u8> = Port::new(COMMAND_PORT);
+	let mut data: Port<u8> = Port::new(CHANNEL_0_DATA_PORT);
+	x86_64::instructions::interrupts::without_interrupts(|| {
+		unsafe {
+			command.write(CHANNEL_0_RATE_GENERATOR);
+			data.write((divisor & 0xFF) as u8);
+			data.write((divisor >> 8) as u8);
+		}
+	});
+}
+
+const CHANNEL_2_DATA_PORT: u16 = 0x42;
+/// Channel 2, low byte then high byte, mode 0 (interrupt on terminal count), binary counting
+const CHANNEL_2_ONE_SHOT: u8 = 0b1011_0000;
+/// Bit 0 gates channel 2, bit 1 connects it to the speaker, bit 5 is the output of channel 2
+const SPEAKER_CONTROL_PORT: u16 = 0x61;
+
+/// Run channel 2 for `counts` counts of the base frequency, and return how many TSC cycles passed meanwhile.
+///
+/// Channel 2 is used since nothing else is using it (the speaker is kept disconnected),
+/// and its output can be polled without needing interrupts.
+pub fn measure_tsc_cycles(counts: u16) -> u64 {
+	let mut command: Port<u8> = Port::new(COMMAND_PORT);
+	let mut data: Port<u8> = Port::new(CHANNEL_2_DATA_PORT);
+	let mut control: Port<u8> = Port::new(SPEAKER_CONTROL_PORT);
+	x86_64::instructions::interrupts::without_interrupts(|| {
+		unsafe {
+			// Gate off while setting up, and keep the speaker quiet
+			let old_control = control.read();
+			control.write(old_control & !0b11);
+			command.write(CHANNEL_2_ONE_SHOT);
+			data.write((counts & 0xFF) as u8);
+			data.write((counts >> 8) as u8);
+			
+			// Counting starts when the gate goes high
+			control.write((old_control & !0b10) | 0b1);
+			let start = core::arch::x86_64::_rdtsc();
+			while control.read() & 0b10_0000 == 0 {}
+			let end = core::arch::x86_64::_rdtsc();
+			
+			control.write(old_control);
+			end - start
+		}
+	})
+}
\ No newline at end of file
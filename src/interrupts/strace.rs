@@ -0,0 +1,125 @@
+use core::convert::TryFrom;
+use core::fmt::{self, Display, Formatter};
+use spin::Mutex;
+use crate::processes::{Name, Pid};
+use crate::serial_println;
+use super::syscall::{SyscallCommand, SyscallResult};
+
+/// Which syscalls get written to the serial port
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TraceFilter {
+	Off,
+	All,
+	/// Only the ones made by this process or thread
+	Pid(Pid),
+}
+
+impl TraceFilter {
+	/// How it's passed to the `SetSyscallTrace` syscall, a mode and a pid
+	pub fn to_args(self) -> [u64; 2] {
+		match self {
+			TraceFilter::Off => [0, 0],
+			TraceFilter::All => [1, 0],
+			TraceFilter::Pid(pid) => [2, pid],
+		}
+	}
+	
+	pub fn from_args(mode: u64, pid: u64) -> Option<TraceFilter> {
+		match mode {
+			0 => Some(TraceFilter::Off),
+			1 => Some(TraceFilter::All),
+			2 => Some(TraceFilter::Pid(pid)),
+			_ => None,
+		}
+	}
+	
+	fn matches(self, pid: Pid) -> bool {
+		match self {
+			TraceFilter::Off => false,
+			TraceFilter::All => true,
+			TraceFilter::Pid(traced) => traced == pid,
+		}
+	}
+}
+
+static TRACE_FILTER: Mutex<TraceFilter> = Mutex::new(TraceFilter::Off);
+
+pub fn set_filter(filter: TraceFilter) {
+	*TRACE_FILTER.lock() = filter;
+}
+
+/// Write one line for a syscall, if the filter lets it through. The line is `strace` followed by space separated
+/// `key=value` fields, always in this order:
+///
+/// `strace tick=<ticks> cpu=<index> pid=<pid> name=<name> call=<SyscallCommand> args=<6 hex args> ret=<value>`
+///
+/// `ret` is the value in decimal, or the negated errno followed by `err=<SyscallError>`, or `none` if no answer
+/// goes back: a `Terminate`, or a blocking FIFO call that is made again once the caller is woken up.
+/// Unknown syscall numbers show up as `call=<number>`.
+pub fn trace(tick: usize, pid: Pid, name: Name, call_num: u64, args: &[u64; 6], result: Option<SyscallResult>) {
+	if !TRACE_FILTER.lock().matches(pid) {
+		return;
+	}
+	serial_println!("strace tick={} cpu={} pid={} name={} call={} args={} ret={}",
+					tick, crate::smp::cpu_index(), pid, name, Call(call_num), HexArgs(args), Ret(result));
+}
+
+struct Call(u64);
+
+impl Display for Call {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		match SyscallCommand::try_from(self.0) {
+			Ok(call) => write!(f, "{:?}", call),
+			Err(_) => write!(f, "{}", self.0),
+		}
+	}
+}
+
+struct HexArgs<'a>(&'a [u64; 6]);
+
+impl Display for HexArgs<'_> {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		for (i, arg) in self.0.iter().enumerate() {
+			if i != 0 {
+				write!(f, ",")?;
+			}
+			write!(f, "{:#x}", arg)?;
+		}
+		Ok(())
+	}
+}
+
+struct Ret(Option<SyscallResult>);
+
+impl Display for Ret {
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		match self.0 {
+			Some(Ok(value)) => write!(f, "{}", value),
+			Some(Err(err)) => write!(f, "-{} err={:?}", err as u64, err),
+			None => write!(f, "none"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::TraceFilter;
+	use crate::{serial_print, serial_println};
+	
+	#[test_case]
+	fn test_trace_filter() {
+		serial_print!("test_trace_filter... ");
+		
+		for &filter in &[TraceFilter::Off, TraceFilter::All, TraceFilter::Pid(7)] {
+			let [mode, pid] = filter.to_args();
+			assert_eq!(TraceFilter::from_args(mode, pid), Some(filter));
+		}
+		assert_eq!(TraceFilter::from_args(3, 0), None);
+		assert!(!TraceFilter::Off.matches(7));
+		assert!(TraceFilter::All.matches(7));
+		assert!(TraceFilter::Pid(7).matches(7));
+		assert!(!TraceFilter::Pid(7).matches(8));
+		
+		serial_println!("[ok]");
+	}
+}
\ No newline at end of file
@@ -0,0 +1,765 @@
+use num_enum::TryFromPrimitive;
+use core::convert::TryFrom;
+use core::mem::MaybeUninit;
+use alloc::vec::Vec;
+use x86_64::VirtAddr;
+use crate::processes::{PROCESS_MANAGER, SchedulingLevel, Name, Pid, Tid, SporadicPriority, DeadlineParams, DeviceBudget,
+					   DeviceOverrunPolicy, CreateProcessError, DeviceAdmissionError, DeadlineAdmissionError,
+					   PeriodicOrderError, ProcessInfo, CpuUsage};
+use crate::processes::process::ProcessStatus;
+use crate::memory::user::{self, BadAddress, PlainData};
+use crate::sync::{SEMAPHORE_STORE, SemaphoreId, Semaphore};
+use crate::ipc::{self, FifoKey, Fifo, FifoAccess, MessageError};
+use super::strace::{self, TraceFilter};
+
+/// Arguments go in rdi, rsi, rdx, r10, r8 and r9 (like Linux, rcx and r11 are taken by the syscall instruction),
+/// the result comes back in rax.
+#[derive(Debug, Copy, Clone, TryFromPrimitive)]
+#[repr(u64)]
+pub enum SyscallCommand {
+	Yield = 10,
+	Terminate,
+	/// Temporary solution to stop the testers.
+	TerminateEverythingElse,
+	/// Sleep for arg1 ticks
+	Sleep,
+	/// Sleep until the tick count reaches arg1
+	SleepUntil,
+	/// Wait on semaphore arg1, blocking until it's acquired
+	SemWait,
+	/// Signal semaphore arg1, waking up the first waiter
+	SemSignal,
+	/// Block until thread arg1 has ended
+	ThreadJoin,
+	/// Returns the arg the current process was created with
+	GetParam,
+	/// Returns the tick count
+	GetTicks,
+	/// Returns the nanoseconds since boot
+	TimeNs,
+	/// Create a process from the `CreateParams` at arg1, returns its pid
+	Create,
+	/// Start thread arg1 (the function) with arg2 as its param, returns its tid. `BadAddress` if arg1 isn't mapped.
+	ThreadSpawn,
+	/// Set the budget of device arg1 to arg2 ticks with `DeviceOverrunPolicy` arg3
+	SetDeviceBudget,
+	/// Set the quantum of sporadic process arg1 to arg3 if arg2 is 1, or back to the default if arg2 is 0
+	SetSporadicQuantum,
+	/// The `RawProcessInfo` of process arg1 goes to arg2
+	ProcessInfo,
+	/// Copy the `RawProcessInfo` of up to arg2 processes to the array at arg1, returns how many processes there are
+	ListProcesses,
+	/// Install the arg2 long array of `PeriodicSlot`s at arg1
+	SetPeriodicOrder,
+	/// Returns the key of a new FIFO holding up to arg1 bytes
+	InitFifo,
+	/// Write as much of the arg3 bytes at arg2 to FIFO arg1 as fits, returns how many were written.
+	/// Blocks while it's full, or returns `WouldBlock` if arg4 is 1. `BrokenPipe` once nothing can read it.
+	FifoWrite,
+	/// Read up to arg3 bytes from FIFO arg1 into arg2, returns how many were read (0 once it has ended).
+	/// Blocks while it's empty, or returns `WouldBlock` if arg4 is 1.
+	FifoRead,
+	/// Create semaphore arg1 with a count of arg2
+	InitSem,
+	/// Remove semaphore arg1, which can't be in use
+	DropSem,
+	/// Set which syscalls get traced over serial, see `TraceFilter::to_args`
+	SetSyscallTrace,
+	/// Stop writing to FIFO arg1, its readers see the end once every writer has stopped
+	FifoCloseWrite,
+	/// Give process arg2 the `FifoAccess` arg3 to FIFO arg1, only its owner can
+	FifoGrant,
+	/// Give up all access to FIFO arg1, it's removed once nobody has any left
+	FifoClose,
+	/// Returns the key of a new message queue holding up to arg1 messages of up to arg2 bytes.
+	/// It's used with the FIFO calls, but each write is one message that a single read gets whole.
+	InitMessageQueue,
+}
+
+/// Errors a syscall can return, numbered like the matching Linux errno values.
+/// They go back to the caller negated in rax, so the top `MAX_ERRNO` values are errors and anything else is a result.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, TryFromPrimitive)]
+#[repr(u64)]
+pub enum SyscallError {
+	/// No process or thread the caller can use with that ID, ESRCH
+	NoSuchProcess = 3,
+	/// Would have to block, but the caller asked not to, EAGAIN
+	WouldBlock = 11,
+	/// Not allowed to use it that way, like reading a FIFO without access, EACCES
+	PermissionDenied = 13,
+	/// It's being used, like a semaphore something is waiting on, or the CPU time a new device or deadline process
+	/// would need is already taken, EBUSY (like Linux's deadline admission)
+	Busy = 16,
+	/// A pointer argument the caller can't use, EFAULT
+	BadAddress = 14,
+	/// Something with that ID already exists, EEXIST
+	AlreadyExists = 17,
+	/// An argument didn't make sense, like a semaphore that doesn't exist, EINVAL
+	InvalidArgument = 22,
+	/// Writing to a FIFO that nothing can read anymore, EPIPE
+	BrokenPipe = 32,
+	/// Not a syscall the kernel knows about, ENOSYS
+	NoSys = 38,
+	/// A message too long for the queue, or for the buffer it's read into, EMSGSIZE
+	MessageTooLong = 90,
+}
+
+const MAX_ERRNO: u64 = 4095;
+
+/// Length of the `syscall` instruction, to go back and make it again
+const SYSCALL_INSTRUCTION_LENGTH: u64 = 2;
+
+pub type SyscallResult = Result<u64, SyscallError>;
+
+impl From<BadAddress> for SyscallError {
+	fn from(_: BadAddress) -> Self {
+		SyscallError::BadAddress
+	}
+}
+
+impl From<MessageError> for SyscallError {
+	fn from(err: MessageError) -> Self {
+		match err {
+			MessageError::Empty => SyscallError::InvalidArgument,
+			MessageError::TooLong => SyscallError::MessageTooLong,
+		}
+	}
+}
+
+impl From<CreateProcessError> for SyscallError {
+	fn from(err: CreateProcessError) -> Self {
+		match err {
+			CreateProcessError::NameTaken(_) => SyscallError::AlreadyExists,
+			CreateProcessError::DeviceUnschedulable(err) => err.into(),
+			CreateProcessError::DeadlineUnschedulable(err) => err.into(),
+			CreateProcessError::UnknownPeriodicName(_) | CreateProcessError::InvalidPriority(_)
+			| CreateProcessError::MissingDeadlineParams => SyscallError::InvalidArgument,
+		}
+	}
+}
+
+impl From<DeviceAdmissionError> for SyscallError {
+	fn from(err: DeviceAdmissionError) -> Self {
+		match err {
+			DeviceAdmissionError::Overutilized { .. } | DeviceAdmissionError::PeriodCollision { .. }
+			| DeviceAdmissionError::FrameOverload { .. } => SyscallError::Busy,
+			DeviceAdmissionError::ZeroPeriod | DeviceAdmissionError::BudgetExceedsPeriod { .. }
+			| DeviceAdmissionError::NotADevice(_) => SyscallError::InvalidArgument,
+		}
+	}
+}
+
+impl From<DeadlineAdmissionError> for SyscallError {
+	fn from(err: DeadlineAdmissionError) -> Self {
+		match err {
+			DeadlineAdmissionError::Overutilized { .. } => SyscallError::Busy,
+			DeadlineAdmissionError::ZeroPeriod | DeadlineAdmissionError::InvalidDeadline { .. }
+			| DeadlineAdmissionError::InvalidBudget { .. } => SyscallError::InvalidArgument,
+		}
+	}
+}
+
+impl From<PeriodicOrderError> for SyscallError {
+	fn from(err: PeriodicOrderError) -> Self {
+		match err {
+			// A running periodic process still needs its slot
+			PeriodicOrderError::UnusedName(_) => SyscallError::Busy,
+			PeriodicOrderError::Empty | PeriodicOrderError::ZeroLengthSlot(_) => SyscallError::InvalidArgument,
+		}
+	}
+}
+
+/// Everything `os_create` and friends need, passed by pointer since it doesn't fit in the registers.
+/// Only plain integers, the kernel checks the level and that the function is mapped before using them.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct CreateParams {
+	level: u64,
+	name: Name,
+	arg: i32,
+	/// Only used by sporadic processes
+	priority: SporadicPriority,
+	/// Only used by deadline processes, `deadline` is ignored if this is 0
+	has_deadline: u64,
+	deadline: DeadlineParams,
+	/// Only used by devices, they get the default budget if this is 0
+	has_budget: u64,
+	budget_ticks: u64,
+	/// A `DeviceOverrunPolicy`
+	budget_policy: u64,
+	program_start: u64,
+}
+
+unsafe impl PlainData for CreateParams {}
+unsafe impl PlainData for DeadlineParams {}
+
+impl CreateParams {
+	pub fn new(level: SchedulingLevel, name: Name, arg: i32, priority: SporadicPriority,
+			   deadline: Option<DeadlineParams>, program_start: extern "C" fn()) -> CreateParams {
+		CreateParams {
+			level: level as u64,
+			name,
+			arg,
+			priority,
+			has_deadline: deadline.is_some() as u64,
+			deadline: deadline.unwrap_or(DeadlineParams { period: 0, relative_deadline: 0, budget: 0 }),
+			has_budget: 0,
+			budget_ticks: 0,
+			budget_policy: 0,
+			program_start: program_start as usize as u64,
+		}
+	}
+	
+	/// Create the device with `budget` already in place, so it never runs with the default one
+	pub fn with_device_budget(mut self, budget: DeviceBudget) -> CreateParams {
+		self.has_budget = 1;
+		self.budget_ticks = budget.ticks as u64;
+		self.budget_policy = budget.policy as u64;
+		self
+	}
+}
+
+/// One slot of a periodic order, as `os_set_periodic_order` passes it
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct PeriodicSlot {
+	pub name: Name,
+	pub ticks: u64,
+}
+
+unsafe impl PlainData for PeriodicSlot {}
+
+/// `ProcessInfo` as the kernel copies it out, every field a number (0 is no owner, since pids start at 1)
+#[derive(Clone, Copy, Default)]
+#[repr(C)]
+pub struct RawProcessInfo {
+	pid: u64,
+	name: u64,
+	level: u64,
+	status: u64,
+	arg: u64,
+	sporadic_priority: u64,
+	device_overruns: u64,
+	deadline_misses: u64,
+	ticks: u64,
+	user_ns: u64,
+	kernel_ns: u64,
+	preemptions: u64,
+	yields: u64,
+	owner: u64,
+}
+
+unsafe impl PlainData for RawProcessInfo {}
+
+impl From<ProcessInfo> for RawProcessInfo {
+	fn from(info: ProcessInfo) -> Self {
+		RawProcessInfo {
+			pid: info.pid,
+			name: info.name,
+			level: info.level as u64,
+			status: info.status as u64,
+			arg: info.arg as u32 as u64,
+			sporadic_priority: info.sporadic_priority as u64,
+			device_overruns: info.device_overruns as u64,
+			deadline_misses: info.deadline_misses as u64,
+			ticks: info.usage.ticks as u64,
+			user_ns: info.usage.user_ns,
+			kernel_ns: info.usage.kernel_ns,
+			preemptions: info.usage.preemptions as u64,
+			yields: info.usage.yields as u64,
+			owner: info.owner.unwrap_or(0),
+		}
+	}
+}
+
+impl RawProcessInfo {
+	/// Back into a `ProcessInfo`, for the syscall stubs
+	pub fn decode(self) -> ProcessInfo {
+		ProcessInfo {
+			pid: self.pid,
+			name: self.name,
+			level: SchedulingLevel::try_from(self.level).expect("Unknown scheduling level"),
+			status: ProcessStatus::try_from(self.status).expect("Unknown process status"),
+			arg: self.arg as u32 as i32,
+			sporadic_priority: self.sporadic_priority as SporadicPriority,
+			device_overruns: self.device_overruns as usize,
+			deadline_misses: self.deadline_misses as usize,
+			usage: CpuUsage {
+				ticks: self.ticks as usize,
+				user_ns: self.user_ns,
+				kernel_ns: self.kernel_ns,
+				preemptions: self.preemptions as usize,
+				yields: self.yields as usize,
+			},
+			owner: if self.owner != 0 { Some(self.owner) } else { None },
+		}
+	}
+}
+
+/// What goes back in rax
+fn encode_result(result: SyscallResult) -> u64 {
+	match result {
+		Ok(value) => value,
+		Err(error) => (error as u64).wrapping_neg(),
+	}
+}
+
+/// What the syscall stubs make of rax
+fn decode_result(rax: u64) -> SyscallResult {
+	if rax >= MAX_ERRNO.wrapping_neg() {
+		Err(SyscallError::try_from(rax.wrapping_neg()).expect("Unknown syscall error"))
+	} else {
+		Ok(rax)
+	}
+}
+
+/// Registers of the caller, as `interrupt_push!` leaves them on its stack
+#[allow(dead_code)] // Laid out like the stack, not every register is used
+#[repr(C)]
+struct SavedRegisters {
+	r15: u64,
+	r14: u64,
+	r13: u64,
+	r12: u64,
+	rbp: u64,
+	rbx: u64,
+	r11: u64,
+	r10: u64,
+	r9: u64,
+	r8: u64,
+	rsi: u64,
+	rdi: u64,
+	rdx: u64,
+	rcx: u64,
+	/// Syscall number on the way in, result on the way out (`interrupt_pop!` hands it back to the caller)
+	rax: u64,
+	// The frame `iretq` returns with
+	rip: u64,
+	cs: u64,
+	rflags: u64,
+	rsp: u64,
+	ss: u64,
+}
+
+impl SavedRegisters {
+	fn args(&self) -> [u64; 6] {
+		[self.rdi, self.rsi, self.rdx, self.r10, self.r8, self.r9]
+	}
+	
+	/// Whether the caller runs in ring 3, then its pointers can only point to user accessible pages
+	fn from_user_mode(&self) -> bool {
+		self.cs & 3 == 3
+	}
+}
+
+/// Registers of the process that made the syscall, `stack_p` being its stack pointer once they are pushed
+fn saved_registers(stack_p: usize) -> &'static mut SavedRegisters {
+	unsafe { &mut *(stack_p as *mut SavedRegisters) }
+}
+
+#[inline(never)]
+pub(super) extern "C" fn internal_syscall(stack_p: usize) -> usize {
+	let registers = saved_registers(stack_p);
+	let args = registers.args();
+	let [arg1, arg2, arg3, arg4, _, _] = args;
+	let user_mode = registers.from_user_mode();
+	let raw_call_num = registers.rax;
+	
+	// Held for the whole syscall, so no other CPU can pick up the caller before its FPU state is saved.
+	// Interrupts are off, so the lock can only be held by another CPU, which will let go soon.
+	let mut p_manager = PROCESS_MANAGER.lock();
+	let caller_pid = p_manager.enter_kernel();
+	// Taken now, the caller might be gone (or not current anymore) by the time it's traced
+	let caller_name = p_manager.get_current_process_name();
+	let tick = p_manager.get_time();
+	let stack_addr = VirtAddr::new(stack_p as u64);
+	
+	let call_num = match SyscallCommand::try_from(raw_call_num) {
+		Ok(call_num) => call_num,
+		Err(_) => {
+			let result = Err(SyscallError::NoSys);
+			registers.rax = encode_result(result);
+			strace::trace(tick, caller_pid, caller_name, raw_call_num, &args, Some(result));
+			p_manager.leave_kernel(caller_pid);
+			return stack_p;
+		}
+	};
+	
+	// Set by calls that block until they can go through, they are made again once the caller is woken up
+	let mut restart = false;
+	// The result is written after switching away from the caller, which is fine since the lock
+	// keeps any CPU from switching back to it until we are done
+	let (new_stack_p, result) = match call_num {
+		SyscallCommand::Yield => {
+			(p_manager.yield_current_process(stack_addr), Ok(0))
+		},
+		SyscallCommand::Terminate => {
+			// Nothing to return to, the caller's stack is gone
+			strace::trace(tick, caller_pid, caller_name, raw_call_num, &args, None);
+			let new_stack_p = p_manager.end_current_process().as_u64() as usize;
+			p_manager.leave_kernel(caller_pid);
+			return new_stack_p;
+		}
+		SyscallCommand::TerminateEverythingElse => {
+			p_manager.end_all_other_processes();
+			(stack_addr, Ok(0))
+		}
+		SyscallCommand::Sleep => {
+			let wake_time = p_manager.get_time().saturating_add(arg1 as usize);
+			(p_manager.sleep_current_process(stack_addr, wake_time), Ok(0))
+		}
+		SyscallCommand::SleepUntil => {
+			(p_manager.sleep_current_process(stack_addr, arg1 as usize), Ok(0))
+		}
+		SyscallCommand::SemWait => {
+			let store = SEMAPHORE_STORE.read();
+			match store.get(&(arg1 as SemaphoreId)) {
+				Some(sem) if !sem.wait() => {
+					sem.add_to_wait_queue(p_manager.get_current_process_pid());
+					// We only get scheduled again once a signal hands the semaphore over to us
+					(p_manager.block_current_process(stack_addr), Ok(0))
+				}
+				Some(_) => (stack_addr, Ok(0)), // Acquired straight away
+				None => (stack_addr, Err(SyscallError::InvalidArgument)),
+			}
+		}
+		SyscallCommand::SemSignal => {
+			let store = SEMAPHORE_STORE.read();
+			match store.get(&(arg1 as SemaphoreId)).map(|sem| sem.signal()) {
+				Some(Some(waiting_pid)) => (p_manager.unblock_process(stack_addr, waiting_pid), Ok(0)),
+				Some(None) => (stack_addr, Ok(0)),
+				None => (stack_addr, Err(SyscallError::InvalidArgument)),
+			}
+		}
+		SyscallCommand::ThreadJoin => {
+			if p_manager.can_join(arg1 as Tid) {
+				(p_manager.join_thread(stack_addr, arg1 as Tid), Ok(0))
+			} else {
+				(stack_addr, Err(SyscallError::NoSuchProcess)) // Not one of ours, or it has already ended
+			}
+		}
+		SyscallCommand::GetParam => {
+			// Zero extended, so negative args don't look like errors
+			(stack_addr, Ok(p_manager.get_current_process_arg() as u32 as u64))
+		}
+		SyscallCommand::GetTicks => (stack_addr, Ok(p_manager.get_time() as u64)),
+		SyscallCommand::TimeNs => (stack_addr, Ok(crate::time::time_ns())),
+		SyscallCommand::Create => {
+			let result = user::read_user::<CreateParams>(arg1, user_mode).map_err(SyscallError::from).and_then(|params| {
+				let level = SchedulingLevel::try_from(params.level).map_err(|_| SyscallError::InvalidArgument)?;
+				// At least mapped (and user accessible, for a user caller), so the new process doesn't fault right away
+				user::check_range(params.program_start, 1, false, user_mode)?;
+				let program_start = unsafe { core::mem::transmute::<u64, extern "C" fn()>(params.program_start) };
+				let deadline = if params.has_deadline != 0 { Some(params.deadline) } else { None };
+				let parent = p_manager.get_current_process_owner();
+				let child = if level == SchedulingLevel::Device && params.has_budget != 0 {
+					let policy = DeviceOverrunPolicy::try_from(params.budget_policy)
+						.map_err(|_| SyscallError::InvalidArgument)?;
+					let budget = DeviceBudget { ticks: params.budget_ticks as usize, policy };
+					p_manager.create_device(params.name, params.arg, budget, program_start)?
+				} else {
+					p_manager.create_new_process(level, params.name, params.arg, params.priority, deadline,
+												 program_start)?
+				};
+				// Before it can run, so it never sees the FIFOs without access
+				ipc::inherit_access(parent, child);
+				Ok(child)
+			});
+			(stack_addr, result)
+		}
+		SyscallCommand::ThreadSpawn => {
+			let result = user::check_range(arg1, 1, false, user_mode).map_err(SyscallError::from).and_then(|_| {
+				let program_start = unsafe { core::mem::transmute::<u64, extern "C" fn()>(arg1) };
+				p_manager.spawn_thread(arg2 as u32 as i32, program_start).map_err(SyscallError::from)
+			});
+			(stack_addr, result)
+		}
+		SyscallCommand::SetDeviceBudget => {
+			let result = DeviceOverrunPolicy::try_from(arg3)
+				.map_err(|_| SyscallError::InvalidArgument)
+				.and_then(|policy| {
+					let budget = DeviceBudget { ticks: arg2 as usize, policy };
+					p_manager.set_device_budget(arg1 as Pid, budget).map(|_| 0).map_err(SyscallError::from)
+				});
+			(stack_addr, result)
+		}
+		SyscallCommand::SetSporadicQuantum => {
+			let quantum = if arg2 != 0 { Some(arg3 as usize) } else { None };
+			let result = p_manager.set_sporadic_quantum(arg1 as Pid, quantum)
+				.map(|_| 0)
+				.map_err(|_| SyscallError::InvalidArgument);
+			(stack_addr, result)
+		}
+		SyscallCommand::ProcessInfo => {
+			let result = match p_manager.get_process_info(arg1 as Pid) {
+				Some(info) => user::write_user(arg2, RawProcessInfo::from(info), user_mode)
+					.map(|_| 0)
+					.map_err(SyscallError::from),
+				None => Err(SyscallError::NoSuchProcess),
+			};
+			(stack_addr, result)
+		}
+		SyscallCommand::ListProcesses => {
+			let infos = p_manager.list_processes();
+			// The caller sees from the count if there wasn't room for all of them
+			let copied = infos.iter().take(arg2 as usize).cloned().map(RawProcessInfo::from).collect::<Vec<_>>();
+			let result = user::write_user_slice(arg1, &copied, user_mode)
+				.map(|_| infos.len() as u64)
+				.map_err(SyscallError::from);
+			(stack_addr, result)
+		}
+		SyscallCommand::SetPeriodicOrder => {
+			let result = user::read_user_slice::<PeriodicSlot>(arg1, arg2 as usize, user_mode)
+				.map_err(SyscallError::from)
+				.map(|slots| slots.iter().map(|slot| (slot.name, slot.ticks as usize)).collect())
+				.and_then(|order| p_manager.set_periodic_order(order).map(|_| 0).map_err(SyscallError::from));
+			(stack_addr, result)
+		}
+		SyscallCommand::InitFifo => {
+			use spin::Mutex;
+			
+			let capacity = arg1 as usize;
+			if capacity == 0 || capacity > ipc::MAX_FIFO_CAPACITY {
+				(stack_addr, Err(SyscallError::InvalidArgument))
+			} else {
+				let key = ipc::get_available_fifo_key();
+				let creator = p_manager.get_current_process_owner();
+				ipc::FIFO_POOL.write().insert(key, Mutex::new(Fifo::new(capacity, creator)));
+				(stack_addr, Ok(key as u64))
+			}
+		}
+		SyscallCommand::InitMessageQueue => {
+			use spin::Mutex;
+			
+			let (max_messages, max_len) = (arg1 as usize, arg2 as usize);
+			let size = max_messages.checked_mul(max_len).unwrap_or(usize::MAX);
+			if max_messages == 0 || max_len == 0 || max_len > ipc::MAX_MESSAGE_LEN || size > ipc::MAX_FIFO_CAPACITY {
+				(stack_addr, Err(SyscallError::InvalidArgument))
+			} else {
+				let key = ipc::get_available_fifo_key();
+				let creator = p_manager.get_current_process_owner();
+				let queue = Fifo::new_message_queue(max_messages, max_len, creator);
+				ipc::FIFO_POOL.write().insert(key, Mutex::new(queue));
+				(stack_addr, Ok(key as u64))
+			}
+		}
+		SyscallCommand::FifoWrite => {
+			let fifo_pool = ipc::FIFO_POOL.read();
+			let len = arg3 as usize;
+			match (fifo_pool.get(&(arg1 as FifoKey)), user::check_range(arg2, len, false, user_mode)) {
+				(None, _) => (stack_addr, Err(SyscallError::InvalidArgument)),
+				(_, Err(err)) => (stack_addr, Err(err.into())),
+				(Some(fifo), Ok(())) => {
+					let mut fifo = fifo.lock();
+					if !fifo.can_write(p_manager.get_current_process_owner()) {
+						(stack_addr, Err(SyscallError::PermissionDenied))
+					} else if !fifo.has_readers() {
+						// Also where a blocked writer ends up once the last reader has gone away
+						(stack_addr, Err(SyscallError::BrokenPipe))
+					} else {
+						match fifo.writable(len) {
+							Err(err) => (stack_addr, Err(err.into())),
+							Ok(0) if len != 0 => {
+								if arg4 != 0 {
+									(stack_addr, Err(SyscallError::WouldBlock))
+								} else {
+									fifo.wait_to_write(caller_pid);
+									restart = true;
+									(p_manager.block_current_process(stack_addr), Ok(0))
+								}
+							}
+							Ok(count) => match user::read_user_slice::<u8>(arg2, count, user_mode) {
+								Ok(data) => {
+									fifo.write(&data);
+									let readers = fifo.take_waiting_readers();
+									(p_manager.unblock_processes(stack_addr, &readers), Ok(count as u64))
+								}
+								Err(err) => (stack_addr, Err(err.into())),
+							}
+						}
+					}
+				}
+			}
+		}
+		SyscallCommand::FifoRead => {
+			let fifo_pool = ipc::FIFO_POOL.read();
+			let len = arg3 as usize;
+			match (fifo_pool.get(&(arg1 as FifoKey)), user::check_range(arg2, len, true, user_mode)) {
+				(None, _) => (stack_addr, Err(SyscallError::InvalidArgument)),
+				(_, Err(err)) => (stack_addr, Err(err.into())),
+				(Some(fifo), Ok(())) => {
+					let mut fifo = fifo.lock();
+					if !fifo.can_read(p_manager.get_current_process_owner()) {
+						(stack_addr, Err(SyscallError::PermissionDenied))
+					} else if fifo.is_empty() && fifo.has_writers() && len != 0 {
+						if arg4 != 0 {
+							(stack_addr, Err(SyscallError::WouldBlock))
+						} else {
+							fifo.wait_to_read(caller_pid);
+							restart = true;
+							(p_manager.block_current_process(stack_addr), Ok(0))
+						}
+					} else {
+						// 0 once it has ended
+						match fifo.readable(len) {
+							Err(err) => (stack_addr, Err(err.into())),
+							// Only taken out of the FIFO once it made it to the caller
+							Ok(count) => match user::copy_to_user(arg2, &fifo.peek(count), user_mode) {
+								Ok(()) => {
+									fifo.consume(count);
+									let writers = fifo.take_waiting_writers();
+									(p_manager.unblock_processes(stack_addr, &writers), Ok(count as u64))
+								}
+								Err(err) => (stack_addr, Err(err.into())),
+							}
+						}
+					}
+				}
+			}
+		}
+		SyscallCommand::InitSem => {
+			let mut store = SEMAPHORE_STORE.write();
+			if store.contains_key(&(arg1 as SemaphoreId)) {
+				(stack_addr, Err(SyscallError::AlreadyExists))
+			} else {
+				store.insert(arg1 as SemaphoreId, Semaphore::new(arg2 as u32 as i32));
+				(stack_addr, Ok(0))
+			}
+		}
+		SyscallCommand::DropSem => {
+			let mut store = SEMAPHORE_STORE.write();
+			let id = arg1 as SemaphoreId;
+			// Left alone while in use, otherwise its waiters would never be signaled
+			match store.get(&id).map(|sem| sem.is_neutral()) {
+				Some(true) => {
+					store.remove(&id);
+					(stack_addr, Ok(0))
+				}
+				Some(false) => (stack_addr, Err(SyscallError::Busy)),
+				None => (stack_addr, Err(SyscallError::InvalidArgument)),
+			}
+		}
+		SyscallCommand::SetSyscallTrace => {
+			match TraceFilter::from_args(arg1, arg2) {
+				Some(filter) => {
+					strace::set_filter(filter);
+					(stack_addr, Ok(0))
+				}
+				None => (stack_addr, Err(SyscallError::InvalidArgument)),
+			}
+		}
+		SyscallCommand::FifoCloseWrite => {
+			let fifo_pool = ipc::FIFO_POOL.read();
+			match fifo_pool.get(&(arg1 as FifoKey)) {
+				Some(fifo) => {
+					// The readers get to see the end, if that was the last writer
+					let readers = fifo.lock().close_writer(p_manager.get_current_process_owner());
+					(p_manager.unblock_processes(stack_addr, &readers), Ok(0))
+				}
+				None => (stack_addr, Err(SyscallError::InvalidArgument)),
+			}
+		}
+		SyscallCommand::FifoGrant => {
+			let process = p_manager.get_current_process_owner();
+			// Threads use their process's access
+			let grantee = p_manager.get_process_info(arg2 as Pid).map(|info| info.owner.unwrap_or(info.pid));
+			let fifo_pool = ipc::FIFO_POOL.read();
+			match (fifo_pool.get(&(arg1 as FifoKey)), FifoAccess::try_from(arg3), grantee) {
+				(None, _, _) | (_, Err(_), _) => (stack_addr, Err(SyscallError::InvalidArgument)),
+				(_, _, None) => (stack_addr, Err(SyscallError::NoSuchProcess)),
+				(Some(fifo), Ok(access), Some(grantee)) => {
+					let mut fifo = fifo.lock();
+					if fifo.is_owner(process) {
+						// Taking write access away from the last writer ends it
+						let readers = fifo.grant(grantee, access);
+						(p_manager.unblock_processes(stack_addr, &readers), Ok(0))
+					} else {
+						(stack_addr, Err(SyscallError::PermissionDenied))
+					}
+				}
+			}
+		}
+		SyscallCommand::FifoClose => {
+			match ipc::close_fifo(arg1 as FifoKey, p_manager.get_current_process_owner()) {
+				Some(waiters) => (p_manager.unblock_processes(stack_addr, &waiters), Ok(0)),
+				None => (stack_addr, Err(SyscallError::InvalidArgument)),
+			}
+		}
+	};
+	
+	if restart {
+		// Always switched away, so it comes back through iretq, which uses the rip in the frame
+		registers.rip -= SYSCALL_INSTRUCTION_LENGTH;
+		registers.rax = raw_call_num;
+		strace::trace(tick, caller_pid, caller_name, raw_call_num, &args, None);
+	} else {
+		registers.rax = encode_result(result);
+		strace::trace(tick, caller_pid, caller_name, raw_call_num, &args, Some(result));
+	}
+	p_manager.leave_kernel(caller_pid);
+	new_stack_p.as_u64() as usize
+}
+
+// Reference: https://en.wikibooks.org/wiki/X86_Assembly/Interfacing_with_Linux
+
+/// Make syscall `call_num` with up to 6 arguments
+#[inline(always)]
+pub fn syscall(call_num: SyscallCommand, args: &[u64]) -> SyscallResult {
+	assert!(args.len() <= 6, "Syscalls only take 6 arguments");
+	let mut all_args = [0u64; 6];
+	all_args[..args.len()].copy_from_slice(args);
+	
+	let ret: u64;
+	unsafe {
+		llvm_asm!("syscall" : "={rax}" (ret) : "{rax}" (call_num as u64),
+			"{rdi}" (all_args[0]), "{rsi}" (all_args[1]), "{rdx}" (all_args[2]),
+			"{r10}" (all_args[3]), "{r8}" (all_args[4]), "{r9}" (all_args[5]) :
+			"rcx", "r11", "memory" : "volatile");
+	}
+	decode_result(ret)
+}
+
+/// For syscalls that answer with more than fits in rax, the kernel writes the answer (a `T`)
+/// to a pointer passed after `args`
+pub fn syscall_out<T: PlainData>(call_num: SyscallCommand, args: &[u64]) -> Result<T, SyscallError> {
+	let mut out = MaybeUninit::<T>::uninit();
+	let mut all_args = [0u64; 6];
+	all_args[..args.len()].copy_from_slice(args);
+	all_args[args.len()] = out.as_mut_ptr() as u64;
+	syscall(call_num, &all_args[..=args.len()])?;
+	// Only written when the syscall went through
+	Ok(unsafe { out.assume_init() })
+}
+
+#[cfg(test)]
+mod test {
+	use super::{encode_result, decode_result, SyscallError};
+	use crate::{serial_print, serial_println};
+	
+	#[test_case]
+	fn test_syscall_result_encoding() {
+		serial_print!("test_syscall_result_encoding... ");
+		
+		for &result in &[Ok(0), Ok(1234), Ok(u64::MAX - 4096), Err(SyscallError::NoSys),
+			Err(SyscallError::InvalidArgument), Err(SyscallError::NoSuchProcess)] {
+			assert_eq!(decode_result(encode_result(result)), result);
+		}
+		assert_eq!(encode_result(Err(SyscallError::NoSys)), -38i64 as u64);
+		
+		serial_println!("[ok]");
+	}
+	
+	#[test_case]
+	fn test_invalid_syscall() {
+		serial_print!("test_invalid_syscall... ");
+		
+		let ret: u64;
+		unsafe {
+			llvm_asm!("syscall" : "={rax}" (ret) : "{rax}" (9999u64) :
+				"rcx", "r11", "memory" : "volatile");
+		}
+		assert_eq!(decode_result(ret), Err(SyscallError::NoSys));
+		
+		serial_println!("[ok]");
+	}
+}
\ No newline at end of file
@@ -0,0 +1,119 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use crate::kernel::{os_init_message_queue, os_send, os_try_send, os_recv, os_try_recv, os_close_write};
+use super::{FifoKey, FifoError};
+
+#[derive(Debug)]
+pub enum ChannelError {
+	Queue(FifoError),
+	/// The value didn't serialize, or a message didn't deserialize into a `T`
+	Postcard(postcard::Error),
+}
+
+impl From<FifoError> for ChannelError {
+	fn from(err: FifoError) -> Self {
+		ChannelError::Queue(err)
+	}
+}
+
+impl From<postcard::Error> for ChannelError {
+	fn from(err: postcard::Error) -> Self {
+		ChannelError::Postcard(err)
+	}
+}
+
+/// Create a message queue for `T`s, holding up to `max_messages` of them serialized into `max_len` bytes each.
+/// Both ends can be rebuilt from the key in other processes with `Sender::new` and `Receiver::new`.
+/// The caller can send on it, so if it only receives it has to close its `Sender` before `recv` can see the end.
+pub fn channel<T: Serialize + DeserializeOwned>(max_messages: usize, max_len: usize)
+	-> Result<(Sender<T>, Receiver<T>), FifoError> {
+	let key = os_init_message_queue(max_messages, max_len)?;
+	Ok((Sender::new(key), Receiver::new(key)))
+}
+
+/// Sends each `T` as one postcard serialized message
+pub struct Sender<T> {
+	key: FifoKey,
+	_marker: PhantomData<fn(T)>,
+}
+
+impl<T: Serialize> Sender<T> {
+	pub fn new(key: FifoKey) -> Sender<T> {
+		Sender { key, _marker: PhantomData }
+	}
+	
+	pub fn key(&self) -> FifoKey {
+		self.key
+	}
+	
+	/// Blocks while the queue is full
+	pub fn send(&self, value: &T) -> Result<(), ChannelError> {
+		os_send(self.key, &serialize(value)?)?;
+		Ok(())
+	}
+	
+	pub fn try_send(&self, value: &T) -> Result<(), ChannelError> {
+		os_try_send(self.key, &serialize(value)?)?;
+		Ok(())
+	}
+	
+	/// Done sending, the receiver gets `None` once every process that can send has closed (or ended)
+	pub fn close(self) -> Result<(), FifoError> {
+		os_close_write(self.key)
+	}
+}
+
+/// Postcard leaves a unit (or anything else without data) empty, and empty messages aren't allowed
+fn serialize<T: Serialize>(value: &T) -> Result<Vec<u8>, ChannelError> {
+	let mut bytes = postcard::to_allocvec(value)?;
+	if bytes.is_empty() {
+		bytes.push(0); // Ignored when deserializing, it doesn't need any bytes
+	}
+	Ok(bytes)
+}
+
+/// Receives the `T`s a `Sender` sent, each from one whole message
+pub struct Receiver<T> {
+	key: FifoKey,
+	/// Big enough for any message
+	buf: Vec<u8>,
+	_marker: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> Receiver<T> {
+	pub fn new(key: FifoKey) -> Receiver<T> {
+		Receiver { key, buf: vec![0; super::MAX_MESSAGE_LEN], _marker: PhantomData }
+	}
+	
+	pub fn key(&self) -> FifoKey {
+		self.key
+	}
+	
+	/// Blocks until there is a message, None once every sender has closed the queue
+	pub fn recv(&mut self) -> Result<Option<T>, ChannelError> {
+		let len = os_recv(self.key, &mut self.buf)?;
+		self.deserialize(len)
+	}
+	
+	/// This process won't send anything, so it doesn't keep its own `recv` from seeing the end.
+	/// Needed whenever it can send too, like when it inherited its access from the process that created the queue.
+	pub fn close_sending(&self) -> Result<(), FifoError> {
+		os_close_write(self.key)
+	}
+	
+	/// `WouldBlock` if there's nothing yet
+	pub fn try_recv(&mut self) -> Result<Option<T>, ChannelError> {
+		let len = os_try_recv(self.key, &mut self.buf)?;
+		self.deserialize(len)
+	}
+	
+	fn deserialize(&self, len: usize) -> Result<Option<T>, ChannelError> {
+		match len {
+			0 => Ok(None),
+			len => Ok(Some(postcard::from_bytes(&self.buf[..len])?)),
+		}
+	}
+}
\ No newline at end of file
@@ -0,0 +1,512 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use spin::Mutex;
+use spin::RwLock;
+use lazy_static::lazy_static;
+use alloc::collections::{BTreeMap, BTreeSet};
+use core::sync::atomic::{AtomicU32, Ordering};
+use num_enum::TryFromPrimitive;
+use crate::processes::Pid;
+
+pub mod channel;
+
+pub type FifoKey = u32;
+
+/// Most bytes a FIFO can hold, they live on the kernel heap. Message queues can't hold more either.
+pub const MAX_FIFO_CAPACITY: usize = 1 << 16;
+/// Longest message a message queue can be created for
+pub const MAX_MESSAGE_LEN: usize = 4096;
+
+lazy_static! {
+	pub static ref FIFO_POOL: RwLock<BTreeMap<FifoKey, Mutex<Fifo>>> = create_fifo_pool();
+}
+
+fn create_fifo_pool() -> RwLock<BTreeMap<FifoKey, Mutex<Fifo>>> {
+	RwLock::new(BTreeMap::new())
+}
+
+pub fn get_available_fifo_key() -> FifoKey {
+	static FIFO_KEY_COUNTER: AtomicU32 = AtomicU32::new(1000);
+	
+	FIFO_KEY_COUNTER.fetch_add(1, Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FifoError {
+	/// No FIFO with that key
+	NoSuchFifo,
+	/// Only from the non blocking calls, there was nothing to read or no space to write
+	WouldBlock,
+	/// A capacity of 0, or more than `MAX_FIFO_CAPACITY`
+	InvalidCapacity,
+	/// The buffer isn't mapped (or writable) for the caller
+	BadAddress,
+	/// The caller's process isn't the owner, and wasn't granted that kind of access
+	PermissionDenied,
+	/// Granting access to a process that doesn't exist
+	NoSuchProcess,
+	/// Sending a message longer than the queue takes, or receiving one into a buffer that is too small
+	MessageTooLong,
+	/// Sending an empty message, it would look like the end
+	EmptyMessage,
+	/// Writing when no process can read it anymore
+	BrokenPipe,
+}
+
+/// Why a message can't go through, no matter how long the caller waits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageError {
+	Empty,
+	/// Longer than the queue takes when sending, longer than the buffer when receiving
+	TooLong,
+}
+
+/// What a `Fifo` holds, `capacity` is in bytes or in messages
+#[derive(Debug)]
+enum Buffer {
+	Bytes(VecDeque<u8>),
+	/// Each write is one message, read whole by one read
+	Messages {
+		messages: VecDeque<Vec<u8>>,
+		max_len: usize,
+	},
+}
+
+/// What a process other than the owner can do with a FIFO
+#[derive(Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+#[repr(u64)]
+pub enum FifoAccess {
+	Read = 1,
+	Write = 2,
+	ReadWrite = 3,
+}
+
+impl FifoAccess {
+	pub fn allows_read(self) -> bool {
+		self != FifoAccess::Write
+	}
+	
+	pub fn allows_write(self) -> bool {
+		self != FifoAccess::Read
+	}
+}
+
+/// Bounded byte stream between processes, or a queue of whole messages.
+///
+/// Only its owner (the process that created it) and the processes it was granted to can use it, threads use
+/// the access of their process. It's removed once the owner and every grantee has closed it or ended.
+///
+/// Its writers are the processes with write access (the owner, and the `Write` or `ReadWrite` grantees) that
+/// haven't closed their write end. Once there are none left and it's empty, reads return 0 bytes for the end.
+/// Like a pipe after a fork, a reader that also has write access (say it inherited it) has to close its write end.
+/// The other way around, once no process can read it anymore writing fails with `BrokenPipe`, like a pipe's EPIPE.
+#[derive(Debug)]
+pub struct Fifo {
+	buffer: Buffer,
+	capacity: usize,
+	owner: Pid,
+	/// False once the owner has closed it or ended
+	owner_open: bool,
+	grants: BTreeMap<Pid, FifoAccess>,
+	/// Stopped writing, but can still read if it's allowed to
+	closed_writers: BTreeSet<Pid>,
+	/// Blocked until there is something to read, or it has ended
+	waiting_readers: Vec<Pid>,
+	/// Blocked until there is space to write, or nothing can read it anymore
+	waiting_writers: Vec<Pid>,
+}
+
+impl Fifo {
+	pub fn new(capacity: usize, creator: Pid) -> Fifo {
+		Fifo::with_buffer(Buffer::Bytes(VecDeque::with_capacity(capacity)), capacity, creator)
+	}
+	
+	/// A queue of up to `max_messages` messages, each 1 to `max_len` bytes long
+	pub fn new_message_queue(max_messages: usize, max_len: usize, creator: Pid) -> Fifo {
+		let buffer = Buffer::Messages { messages: VecDeque::with_capacity(max_messages), max_len };
+		Fifo::with_buffer(buffer, max_messages, creator)
+	}
+	
+	fn with_buffer(buffer: Buffer, capacity: usize, creator: Pid) -> Fifo {
+		Fifo {
+			buffer,
+			capacity,
+			owner: creator,
+			owner_open: true,
+			grants: BTreeMap::new(),
+			closed_writers: BTreeSet::new(),
+			waiting_readers: Vec::new(),
+			waiting_writers: Vec::new(),
+		}
+	}
+	
+	/// What `process` can do with it, None if it can't use it at all
+	pub fn access(&self, process: Pid) -> Option<FifoAccess> {
+		if process == self.owner && self.owner_open {
+			Some(FifoAccess::ReadWrite)
+		} else {
+			self.grants.get(&process).copied()
+		}
+	}
+	
+	pub fn can_read(&self, process: Pid) -> bool {
+		self.access(process).map_or(false, FifoAccess::allows_read)
+	}
+	
+	/// Not after closing its write end
+	pub fn can_write(&self, process: Pid) -> bool {
+		self.access(process).map_or(false, FifoAccess::allows_write) && !self.closed_writers.contains(&process)
+	}
+	
+	/// Only the owner can hand out access, a new grant replaces the old one
+	pub fn is_owner(&self, process: Pid) -> bool {
+		process == self.owner && self.owner_open
+	}
+	
+	/// A new grant also opens the write end again, if it allows writing.
+	/// Returns the blocked processes if it took away the access of the last writer or reader.
+	pub fn grant(&mut self, process: Pid, access: FifoAccess) -> Vec<Pid> {
+		if process == self.owner {
+			return Vec::new();
+		}
+		self.update_access(|fifo| {
+			fifo.grants.insert(process, access);
+			fifo.closed_writers.remove(&process);
+		})
+	}
+	
+	/// Give `child` the access `parent` has, with the write end closed if the parent closed it
+	fn inherit(&mut self, parent: Pid, child: Pid) {
+		if let Some(access) = self.access(parent) {
+			self.grants.insert(child, access);
+			if self.closed_writers.contains(&parent) {
+				self.closed_writers.insert(child);
+			}
+		}
+	}
+	
+	/// `process` is done with it, it loses its access and stops being a writer.
+	/// Returns the blocked readers if that was the last writer, and the blocked writers if it was the last reader.
+	pub fn close(&mut self, process: Pid) -> Vec<Pid> {
+		self.update_access(|fifo| {
+			if process == fifo.owner {
+				fifo.owner_open = false;
+			}
+			fifo.grants.remove(&process);
+			fifo.closed_writers.remove(&process); // Nothing left to close
+		})
+	}
+	
+	/// `writer` stops writing, returns the blocked readers if that was the last writer
+	pub fn close_writer(&mut self, writer: Pid) -> Vec<Pid> {
+		self.update_access(|fifo| {
+			fifo.closed_writers.insert(writer);
+		})
+	}
+	
+	/// Make a change to who can use it. The readers get to see the end if it leaves no writers,
+	/// and the writers get to fail if it leaves no readers.
+	fn update_access(&mut self, change: impl FnOnce(&mut Fifo)) -> Vec<Pid> {
+		let had_writers = self.has_writers();
+		let had_readers = self.has_readers();
+		change(self);
+		let mut woken = Vec::new();
+		if had_writers && !self.has_writers() {
+			woken.append(&mut self.take_waiting_readers());
+		}
+		if had_readers && !self.has_readers() {
+			woken.append(&mut self.take_waiting_writers());
+		}
+		woken
+	}
+	
+	/// Nobody can use it anymore
+	pub fn is_abandoned(&self) -> bool {
+		!self.owner_open && self.grants.is_empty()
+	}
+	
+	/// Bytes, or messages, in it
+	pub fn len(&self) -> usize {
+		match &self.buffer {
+			Buffer::Bytes(bytes) => bytes.len(),
+			Buffer::Messages { messages, .. } => messages.len(),
+		}
+	}
+	
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+	
+	/// Bytes, or messages, that can be written before it's full
+	pub fn space(&self) -> usize {
+		self.capacity - self.len()
+	}
+	
+	/// False once nothing can write to it anymore, so once it's empty nothing will be read from it either
+	pub fn has_writers(&self) -> bool {
+		let owner = Some(self.owner).filter(|_| self.owner_open);
+		let grantees = self.grants.iter()
+			.filter(|(_, access)| access.allows_write())
+			.map(|(&pid, _)| pid);
+		owner.into_iter().chain(grantees).any(|pid| !self.closed_writers.contains(&pid))
+	}
+	
+	/// False once nothing can read it anymore, so nothing written to it would ever be read
+	pub fn has_readers(&self) -> bool {
+		self.owner_open || self.grants.values().any(|&access| access.allows_read())
+	}
+	
+	/// How many bytes of a `len` byte write would go in right now, 0 if it's full.
+	/// A message goes in whole or not at all.
+	pub fn writable(&self, len: usize) -> Result<usize, MessageError> {
+		match &self.buffer {
+			Buffer::Bytes(_) => Ok(len.min(self.space())),
+			Buffer::Messages { max_len, .. } => match len {
+				0 => Err(MessageError::Empty),
+				len if len > *max_len => Err(MessageError::TooLong),
+				_ if self.space() == 0 => Ok(0),
+				len => Ok(len),
+			}
+		}
+	}
+	
+	/// Add as much of `data` as `writable` says fits, returns how much that was
+	pub fn write(&mut self, data: &[u8]) -> usize {
+		let count = self.writable(data.len()).unwrap_or(0);
+		match &mut self.buffer {
+			Buffer::Bytes(bytes) => bytes.extend(&data[..count]),
+			Buffer::Messages { messages, .. } if count != 0 => messages.push_back(data.to_vec()),
+			Buffer::Messages { .. } => {}
+		}
+		count
+	}
+	
+	/// How many bytes a read into a `buf_len` byte buffer gets, 0 if it's empty.
+	/// The next message has to fit in the buffer as a whole.
+	pub fn readable(&self, buf_len: usize) -> Result<usize, MessageError> {
+		match &self.buffer {
+			Buffer::Bytes(bytes) => Ok(buf_len.min(bytes.len())),
+			Buffer::Messages { messages, .. } => match messages.front() {
+				Some(message) if message.len() > buf_len => Err(MessageError::TooLong),
+				Some(message) => Ok(message.len()),
+				None => Ok(0),
+			}
+		}
+	}
+	
+	/// Copy of what a `count` byte read (from `readable`) gets, it stays in the FIFO until `consume`
+	pub fn peek(&self, count: usize) -> Vec<u8> {
+		match &self.buffer {
+			Buffer::Bytes(bytes) => bytes.iter().take(count).cloned().collect(),
+			Buffer::Messages { messages, .. } => messages.front()
+				.filter(|_| count != 0)
+				.cloned()
+				.unwrap_or_default(),
+		}
+	}
+	
+	pub fn consume(&mut self, count: usize) {
+		match &mut self.buffer {
+			Buffer::Bytes(bytes) => {
+				bytes.drain(..count);
+			}
+			Buffer::Messages { messages, .. } if count != 0 => {
+				messages.pop_front();
+			}
+			Buffer::Messages { .. } => {}
+		}
+	}
+	
+	pub fn wait_to_read(&mut self, pid: Pid) {
+		self.waiting_readers.push(pid);
+	}
+	
+	pub fn wait_to_write(&mut self, pid: Pid) {
+		self.waiting_writers.push(pid);
+	}
+	
+	/// Every blocked reader, for when they have something to look at. They retry the read once they run.
+	pub fn take_waiting_readers(&mut self) -> Vec<Pid> {
+		core::mem::replace(&mut self.waiting_readers, Vec::new())
+	}
+	
+	pub fn take_waiting_writers(&mut self) -> Vec<Pid> {
+		core::mem::replace(&mut self.waiting_writers, Vec::new())
+	}
+	
+	fn take_all_waiters(&mut self) -> Vec<Pid> {
+		let mut waiters = self.take_waiting_readers();
+		waiters.append(&mut self.waiting_writers);
+		waiters
+	}
+	
+	fn remove_waiter(&mut self, pid: Pid) {
+		self.waiting_readers.retain(|&c| c != pid);
+		self.waiting_writers.retain(|&c| c != pid);
+	}
+}
+
+/// Take a blocked process that is ending out of every FIFO's wait queues
+pub fn remove_waiter(pid: Pid) {
+	for fifo in FIFO_POOL.read().values() {
+		fifo.lock().remove_waiter(pid);
+	}
+}
+
+/// `process` closes FIFO `key`, which is removed if nobody else can use it.
+/// Returns the blocked processes that have to be woken up, to see the end or that it's gone.
+/// None if there's no such FIFO, or `process` couldn't use it anyway.
+pub fn close_fifo(key: FifoKey, process: Pid) -> Option<Vec<Pid>> {
+	let mut fifo_pool = FIFO_POOL.write();
+	let (woken, abandoned) = {
+		let mut fifo = fifo_pool.get(&key)?.lock();
+		fifo.access(process)?;
+		let mut woken = fifo.close(process);
+		let abandoned = fifo.is_abandoned();
+		if abandoned {
+			woken.append(&mut fifo.take_all_waiters());
+		}
+		(woken, abandoned)
+	};
+	if abandoned {
+		fifo_pool.remove(&key);
+	}
+	Some(woken)
+}
+
+/// Close every FIFO of `process`, which is ending, and remove the ones nobody can use anymore.
+/// Returns the blocked processes that have to be woken up.
+pub fn process_ended(process: Pid) -> Vec<Pid> {
+	let mut fifo_pool = FIFO_POOL.write();
+	let mut woken = Vec::new();
+	let mut abandoned = Vec::new();
+	for (&key, fifo) in fifo_pool.iter() {
+		let mut fifo = fifo.lock();
+		woken.append(&mut fifo.close(process));
+		if fifo.is_abandoned() {
+			woken.append(&mut fifo.take_all_waiters());
+			abandoned.push(key);
+		}
+	}
+	for key in abandoned {
+		fifo_pool.remove(&key);
+	}
+	woken
+}
+
+/// A new process gets the same access as the process that created it, like file descriptors across a fork.
+/// That way a FIFO can be handed to a child through its arg, without it racing the grant.
+pub fn inherit_access(parent: Pid, child: Pid) {
+	for fifo in FIFO_POOL.read().values() {
+		fifo.lock().inherit(parent, child);
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Fifo, FifoAccess, MessageError};
+	use crate::{serial_print, serial_println};
+	
+	#[test_case]
+	fn test_fifo_capacity_and_end() {
+		serial_print!("test_fifo_capacity_and_end... ");
+		
+		let mut fifo = Fifo::new(4, 1);
+		assert_eq!(fifo.write(b"abcdef"), 4);
+		assert_eq!(fifo.space(), 0);
+		assert_eq!(fifo.write(b"g"), 0);
+		assert_eq!(fifo.peek(3), b"abc");
+		fifo.consume(3);
+		assert_eq!(fifo.write(b"gh"), 2);
+		assert_eq!(fifo.peek(10), b"dgh");
+		
+		fifo.grant(2, FifoAccess::Write);
+		fifo.grant(3, FifoAccess::Read);
+		fifo.wait_to_read(3);
+		assert!(fifo.close_writer(1).is_empty());
+		assert!(!fifo.can_write(1));
+		assert!(fifo.close_writer(3).is_empty()); // Couldn't write anyway
+		assert!(fifo.has_writers()); // 2 can still write, even though it hasn't yet
+		assert_eq!(fifo.close_writer(2), [3]);
+		assert!(!fifo.has_writers());
+		
+		serial_println!("[ok]");
+	}
+	
+	#[test_case]
+	fn test_message_queue_boundaries() {
+		serial_print!("test_message_queue_boundaries... ");
+		
+		let mut queue = Fifo::new_message_queue(2, 8, 1);
+		assert_eq!(queue.writable(0), Err(MessageError::Empty));
+		assert_eq!(queue.writable(9), Err(MessageError::TooLong));
+		assert_eq!(queue.write(b"hello"), 5);
+		assert_eq!(queue.write(b"bye"), 3);
+		assert_eq!(queue.writable(1), Ok(0)); // Full, 2 messages
+		
+		assert_eq!(queue.readable(4), Err(MessageError::TooLong)); // Never half a message
+		assert_eq!(queue.readable(64), Ok(5));
+		assert_eq!(queue.peek(5), b"hello");
+		queue.consume(5);
+		assert_eq!(queue.readable(64), Ok(3));
+		assert_eq!(queue.peek(3), b"bye");
+		queue.consume(3);
+		assert_eq!(queue.readable(64), Ok(0));
+		
+		serial_println!("[ok]");
+	}
+	
+	#[test_case]
+	fn test_fifo_access() {
+		serial_print!("test_fifo_access... ");
+		
+		let mut fifo = Fifo::new(4, 1);
+		assert!(fifo.can_read(1) && fifo.can_write(1));
+		assert_eq!(fifo.access(2), None);
+		fifo.grant(2, FifoAccess::Read);
+		assert!(fifo.can_read(2) && !fifo.can_write(2));
+		assert!(!fifo.is_owner(2));
+		
+		fifo.close(1);
+		assert_eq!(fifo.access(1), None);
+		assert!(!fifo.is_abandoned()); // 2 can still read it
+		fifo.close(2);
+		assert!(fifo.is_abandoned());
+		
+		serial_println!("[ok]");
+	}
+	
+	#[test_case]
+	fn test_fifo_without_readers() {
+		serial_print!("test_fifo_without_readers... ");
+		
+		let mut fifo = Fifo::new(1, 1);
+		fifo.grant(2, FifoAccess::Write);
+		fifo.grant(3, FifoAccess::Read);
+		assert_eq!(fifo.write(b"a"), 1);
+		fifo.wait_to_write(2); // Full
+		assert!(fifo.close(1).is_empty()); // 3 can still read it
+		assert!(fifo.has_readers());
+		assert_eq!(fifo.close(3), [2]);
+		assert!(!fifo.has_readers());
+		assert!(!fifo.is_abandoned()); // 2 still has to find out
+		
+		serial_println!("[ok]");
+	}
+	
+	#[test_case]
+	fn test_queue_created_by_receiver() {
+		serial_print!("test_queue_created_by_receiver... ");
+		
+		let mut queue = Fifo::new_message_queue(2, 8, 1);
+		queue.grant(2, FifoAccess::Write);
+		queue.close_writer(1); // Only receives
+		assert!(queue.has_writers(), "Ended before the sender sent anything");
+		assert_eq!(queue.write(b"hi"), 2);
+		queue.close(2); // The sender ended
+		assert!(!queue.has_writers());
+		assert_eq!(queue.readable(8), Ok(2)); // What was sent before the end is still there
+		
+		serial_println!("[ok]");
+	}
+}
\ No newline at end of file
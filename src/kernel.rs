@@ -1,34 +1,44 @@
 #![allow(dead_code)]
 
-use crate::interrupts::{interrupt_init, syscall1, SyscallCommand, syscall_handler};
+use crate::interrupts::{interrupt_init, syscall_handler};
+use crate::interrupts::syscall::{syscall, syscall_out, SyscallCommand, SyscallError, CreateParams, RawProcessInfo,
+								 PeriodicSlot};
+use crate::interrupts::strace::TraceFilter;
 use crate::gdt::gdt_init;
-use crate::{println, eprintln};
+use crate::println;
 use x86_64::instructions::interrupts;
-use spin::Mutex;
 
 use alloc::prelude::v1::*;
+use alloc::vec;
 use x86_64::VirtAddr;
-use crate::processes::{SchedulingLevel, Name};
+use crate::processes::{SchedulingLevel, Name, Pid, DeviceBudget, ProcessInfo, SporadicPriority, DEFAULT_SPORADIC_PRIORITY,
+					   DeadlineParams, Tid};
 use crate::processes::PROCESS_MANAGER;
 use x86_64::registers::rflags::RFlags;
-use core::ops::Deref;
-use crate::ipc::FifoKey;
-use crate::sync::{SemaphoreId, SEMAPHORE_STORE, Semaphore};
-use x86_64::instructions::interrupts::{without_interrupts,
-									   disable as disable_int,
-									   enable as enable_int};
+use x86_64::structures::tss::TaskStateSegment;
+use crate::ipc::{FifoKey, FifoError, FifoAccess};
+use crate::sync::SemaphoreId;
 
 pub fn os_init() {
+	crate::time::tsc_init();
 	interrupt_init();
 	
 	gdt_init();
+	crate::processes::fpu::fpu_init();
 	
+	syscall_init(&*crate::gdt::TSS);
+	crate::smp::smp_init();
+}
+
+/// Point the syscall instruction at `syscall_handler` on this CPU, with `tss` (the TSS of this CPU)
+/// as the `gs` scratch space the handler uses
+pub(crate) fn syscall_init(tss: &'static TaskStateSegment) {
 	use x86_64::registers::model_specific::{LStar, SFMask, KernelGsBase, Star, Efer, EferFlags};
 	// Store syscall location
 	
 	LStar::write(VirtAddr::new(syscall_handler as u64));
 	SFMask::write(RFlags::INTERRUPT_FLAG | RFlags::TRAP_FLAG);
-	KernelGsBase::write(VirtAddr::new(crate::gdt::TSS.deref() as *const _ as u64));
+	KernelGsBase::write(VirtAddr::new(tss as *const _ as u64));
 	
 	unsafe {
 		Star::write_raw(0, crate::gdt::GDT.1.code_selector.0);
@@ -38,9 +48,12 @@ pub fn os_init() {
 
 #[inline(never)]
 pub fn os_start() {
+	let mut p_manager = PROCESS_MANAGER.lock();
+	p_manager.create_idle_process();
 	// println!("test_app: {:x}", test_app as u64);
-	os_create(123, SchedulingLevel::Sporadic, 1,
-			  crate::tests::app_test_runner::run_tests).unwrap();
+	// Not through os_create, there's no process to make the syscall yet
+	p_manager.create_new_process(SchedulingLevel::Sporadic, 1, 123, DEFAULT_SPORADIC_PRIORITY, None,
+								 crate::tests::app_test_runner::run_tests).unwrap();
 	// os_create(123, SchedulingLevel::Periodic, 1, test_app_signals).unwrap();
 	// os_create(123, SchedulingLevel::Sporadic, 4, test_app_signals_recv).unwrap();
 	// os_create(123, SchedulingLevel::Periodic, 4, write_test_app).unwrap();
@@ -58,157 +71,323 @@ pub fn os_start() {
 
 pub extern "C" fn os_terminate() {
 	// println!("We out of there!");
-	syscall1(SyscallCommand::Terminate);
+	syscall(SyscallCommand::Terminate, &[]).expect("Failed to terminate");
 }
 
 pub fn os_yield() {
-	syscall1(SyscallCommand::Yield);
+	syscall(SyscallCommand::Yield, &[]).expect("Failed to yield");
+}
+
+/// Give up the CPU for at least `ticks` timer ticks
+pub fn os_sleep(ticks: usize) {
+	syscall(SyscallCommand::Sleep, &[ticks as u64]).expect("Failed to sleep");
+}
+
+/// Give up the CPU until the timer tick count reaches `tick`, returns straight away if it already has
+pub fn os_sleep_until(tick: usize) {
+	syscall(SyscallCommand::SleepUntil, &[tick as u64]).expect("Failed to sleep");
+}
+
+/// Give up the CPU for at least `us` microseconds, rounded up to whole ticks
+pub fn os_sleep_us(us: u64) {
+	os_sleep(crate::time::us_to_ticks(us));
+}
+
+/// Number of timer ticks since the scheduler started
+pub fn os_get_ticks() -> usize {
+	syscall(SyscallCommand::GetTicks, &[]).expect("Failed to get the ticks") as usize
+}
+
+/// Nanoseconds since boot, from the TSC so it's much finer than a tick.
+/// Never goes backwards on one CPU, but a process that moves to another CPU can see a slightly earlier time.
+pub fn os_time_ns() -> u64 {
+	syscall(SyscallCommand::TimeNs, &[]).expect("Failed to get the time")
+}
+
+/// Microseconds since the scheduler started, only as precise as a tick
+pub fn os_get_uptime_us() -> u64 {
+	crate::time::ticks_to_us(os_get_ticks())
 }
 
 pub fn os_getparam() -> i32 {
-	PROCESS_MANAGER.lock().get_current_process_arg()
+	syscall(SyscallCommand::GetParam, &[]).expect("Failed to get the param") as u32 as i32
 }
 
-pub(crate) fn os_create(arg: i32, level: SchedulingLevel, name: Name, f: extern "C" fn()) -> Result<(), ()> {
-	// No need to turn off interrupts because we lock process_manager
-	let mut p_manager_lock = PROCESS_MANAGER.lock();
-	p_manager_lock.create_new_process(level, name, arg, f)
-	// Ok(())
+/// `AlreadyExists` if a periodic process already has the name, `Busy` if there isn't enough CPU time left for
+/// the new device or deadline process, and `InvalidArgument` for anything else it can't be created with
+fn create(params: CreateParams) -> Result<Pid, SyscallError> {
+	syscall(SyscallCommand::Create, &[&params as *const CreateParams as u64])
 }
 
-pub fn os_init_fifo() -> FifoKey {
-	use crate::ipc;
-	use alloc::collections::VecDeque;
-	
-	let mut fifo_pool = ipc::FIFO_POOL.write();
-	let key = ipc::get_available_fifo_key();
-	fifo_pool.insert(key, Mutex::new(VecDeque::new()));
-	
-	key
+/// Returns the pid of the new process
+pub(crate) fn os_create(arg: i32, level: SchedulingLevel, name: Name, f: extern "C" fn()) -> Result<Pid, SyscallError> {
+	create(CreateParams::new(level, name, arg, DEFAULT_SPORADIC_PRIORITY, None, f))
+}
+
+/// Create a device process with `budget` instead of the default budget. Unlike creating it and then calling
+/// `os_set_device_budget`, the device can't be released before its budget and overrun policy are in place.
+pub(crate) fn os_create_device(arg: i32, name: Name, budget: DeviceBudget, f: extern "C" fn())
+	-> Result<Pid, SyscallError> {
+	create(CreateParams::new(SchedulingLevel::Device, name, arg, DEFAULT_SPORADIC_PRIORITY, None, f)
+		.with_device_budget(budget))
 }
 
-/// Write data to key
+/// Create a sporadic process starting at `priority` in the sporadic queue, where 0 is the highest priority.
 ///
-/// Returns Ok if written, otherwise TODO: error type
-pub fn os_write(key: FifoKey, data: &[u8]) -> Result<(), ()> {
-	use crate::ipc;
-	// TODO: Check for ownership
-	let fifo_pool = ipc::FIFO_POOL.read();
-	let first = fifo_pool.get(&key).ok_or(())?;
-	first.lock().extend(data.iter().cloned());
-	
-	Ok(())
+/// The priority changes over time, processes that use a lot of CPU time get demoted,
+/// and processes that have been waiting a long time get promoted.
+pub(crate) fn os_create_sporadic(arg: i32, name: Name, priority: SporadicPriority, f: extern "C" fn())
+	-> Result<Pid, SyscallError> {
+	create(CreateParams::new(SchedulingLevel::Sporadic, name, arg, priority, None, f))
+}
+
+/// Create a deadline process, which is released every `params.period` ticks and has to yield within
+/// `params.relative_deadline` ticks of each release. Deadline processes run earliest deadline first, after devices.
+///
+/// A job that uses up `params.budget` keeps running but has its deadline pushed back by a period,
+/// so it can't steal time from the other deadline processes. Missed deadlines show up in `os_process_info()`.
+pub(crate) fn os_create_deadline(arg: i32, name: Name, params: DeadlineParams, f: extern "C" fn())
+	-> Result<Pid, SyscallError> {
+	create(CreateParams::new(SchedulingLevel::Deadline, name, arg, DEFAULT_SPORADIC_PRIORITY, Some(params), f))
 }
 
-/// Read into buf TODO: error type
-pub fn os_read(key: FifoKey, buf: &mut [u8]) -> Result<usize, ()> {
-	use crate::ipc;
-	// TODO: Check for ownership
-	let fifo_pool = ipc::FIFO_POOL.read();
-	let first = fifo_pool.get(&key).ok_or(())?;
-	for (idx, data) in buf.iter_mut().enumerate() {
-		if let Some(c) = first.lock().pop_front() {
-			*data = c
-		} else {
-			return Ok(idx)
+/// Start a thread in the current process, running `f` on its own stack with `arg` as its `os_getparam()`.
+///
+/// Threads share everything else with the process (memory, FIFOs, semaphores), and are scheduled
+/// the same way as the process: a thread of a periodic process takes turns with it in its slot,
+/// while threads of the other levels go through admission and get queued as if they were another process.
+/// Threads are ended along with their process.
+pub fn os_thread_spawn(f: extern "C" fn(), arg: i32) -> Result<Tid, SyscallError> {
+	syscall(SyscallCommand::ThreadSpawn, &[f as u64, arg as u32 as u64])
+}
+
+/// Wait for a thread of the current process to end.
+///
+/// Returns Err if `tid` isn't one of the other threads of this process (it might have already ended)
+pub fn os_thread_join(tid: Tid) -> Result<(), ()> {
+	// Returns once the thread has ended
+	syscall(SyscallCommand::ThreadJoin, &[tid]).map(|_| ()).map_err(|_| ())
+}
+
+/// Change how long a device process can run each time it's released, and what happens when it goes over.
+///
+/// Returns `InvalidArgument` if `pid` isn't a device process or the budget doesn't fit in its period,
+/// and `Busy` if the new budget would make the devices unschedulable.
+pub fn os_set_device_budget(pid: Pid, budget: DeviceBudget) -> Result<(), SyscallError> {
+	syscall(SyscallCommand::SetDeviceBudget, &[pid, budget.ticks as u64, budget.policy as u64]).map(|_| ())
+}
+
+/// Set how many ticks a sporadic process runs before the others at its priority level get a turn,
+/// `None` goes back to the default quantum of its level.
+pub fn os_set_sporadic_quantum(pid: Pid, quantum: Option<usize>) -> Result<(), ()> {
+	let args = match quantum {
+		Some(quantum) => [pid, 1, quantum as u64],
+		None => [pid, 0, 0],
+	};
+	syscall(SyscallCommand::SetSporadicQuantum, &args).map(|_| ()).map_err(|_| ())
+}
+
+pub fn os_process_info(pid: Pid) -> Option<ProcessInfo> {
+	match syscall_out::<RawProcessInfo>(SyscallCommand::ProcessInfo, &[pid]) {
+		Ok(info) => Some(info.decode()),
+		Err(SyscallError::NoSuchProcess) => None,
+		Err(err) => panic!("Failed to get the process info: {:?}", err),
+	}
+}
+
+pub fn os_list_processes() -> Vec<ProcessInfo> {
+	let mut capacity = 16;
+	loop {
+		let mut infos = vec![RawProcessInfo::default(); capacity];
+		let count = syscall(SyscallCommand::ListProcesses, &[infos.as_mut_ptr() as u64, capacity as u64])
+			.expect("Failed to list the processes") as usize;
+		if count <= capacity {
+			infos.truncate(count);
+			return infos.into_iter().map(RawProcessInfo::decode).collect();
 		}
+		capacity = count + 8; // Some room in case more get created in the meantime
 	}
-	
-	Ok(buf.len())
+}
+
+/// Install a new periodic order of (Name, ticks) slots.
+///
+/// The new order only takes over at the end of the current frame.
+/// Returns `InvalidArgument` for an empty order or a slot of 0 ticks, and `Busy` if a running periodic process
+/// wouldn't have a slot in it.
+pub fn os_set_periodic_order(periodic_order: Vec<(Name, usize)>) -> Result<(), SyscallError> {
+	let slots = periodic_order.iter()
+		.map(|&(name, ticks)| PeriodicSlot { name, ticks: ticks as u64 })
+		.collect::<Vec<_>>();
+	syscall(SyscallCommand::SetPeriodicOrder, &[slots.as_ptr() as u64, slots.len() as u64]).map(|_| ())
+}
+
+/// Create a FIFO that holds up to `capacity` bytes (at most `MAX_FIFO_CAPACITY`).
+/// The caller can write to it, so readers won't see the end until it calls `os_close_write`.
+///
+/// Only the caller's process owns it, other processes need `os_grant_fifo`. Processes it creates from then on
+/// get the same access though, so it can be passed to them through their arg.
+pub fn os_init_fifo(capacity: usize) -> Result<FifoKey, FifoError> {
+	syscall(SyscallCommand::InitFifo, &[capacity as u64])
+		.map(|key| key as FifoKey)
+		.map_err(|err| match err {
+			SyscallError::InvalidArgument => FifoError::InvalidCapacity,
+			err => fifo_error(err),
+		})
+}
+
+fn fifo_error(err: SyscallError) -> FifoError {
+	match err {
+		SyscallError::WouldBlock => FifoError::WouldBlock,
+		SyscallError::BadAddress => FifoError::BadAddress,
+		SyscallError::PermissionDenied => FifoError::PermissionDenied,
+		SyscallError::NoSuchProcess => FifoError::NoSuchProcess,
+		SyscallError::MessageTooLong => FifoError::MessageTooLong,
+		SyscallError::BrokenPipe => FifoError::BrokenPipe,
+		_ => FifoError::NoSuchFifo,
+	}
+}
+
+fn fifo_write(key: FifoKey, data: &[u8], blocking: bool) -> Result<usize, FifoError> {
+	syscall(SyscallCommand::FifoWrite, &[key as u64, data.as_ptr() as u64, data.len() as u64, !blocking as u64])
+		.map(|count| count as usize)
+		.map_err(fifo_error)
+}
+
+fn fifo_read(key: FifoKey, buf: &mut [u8], blocking: bool) -> Result<usize, FifoError> {
+	syscall(SyscallCommand::FifoRead, &[key as u64, buf.as_mut_ptr() as u64, buf.len() as u64, !blocking as u64])
+		.map(|count| count as usize)
+		.map_err(fifo_error)
+}
+
+/// Write all of `data`, blocking whenever the FIFO is full until a reader makes space.
+///
+/// Data that doesn't fit in one go can end up interleaved with what other writers wrote in the meantime.
+/// `BrokenPipe` once no process can read it anymore, even if the caller was blocked waiting for space.
+pub fn os_write(key: FifoKey, data: &[u8]) -> Result<(), FifoError> {
+	let mut written = 0;
+	while written < data.len() {
+		written += fifo_write(key, &data[written..], true)?;
+	}
+	Ok(())
+}
+
+/// Write as much of `data` as fits without blocking, returns how much that was.
+/// `WouldBlock` if the FIFO is full.
+pub fn os_try_write(key: FifoKey, data: &[u8]) -> Result<usize, FifoError> {
+	fifo_write(key, data, false)
+}
+
+/// Read into `buf`, blocking until there is at least one byte. Returns how many bytes were read,
+/// 0 meaning every process that can write to it has closed its write end (or ended) and there is nothing left,
+/// or that `buf` is empty. That includes the caller, if it can write to it too.
+pub fn os_read(key: FifoKey, buf: &mut [u8]) -> Result<usize, FifoError> {
+	fifo_read(key, buf, true)
+}
+
+/// Read whatever is there without blocking, `WouldBlock` if the FIFO is empty but still has writers
+pub fn os_try_read(key: FifoKey, buf: &mut [u8]) -> Result<usize, FifoError> {
+	fifo_read(key, buf, false)
+}
+
+/// Stop writing to the FIFO, for good unless it's granted write access again. Once every process with write access
+/// has (or has ended), its readers get the end.
+pub fn os_close_write(key: FifoKey) -> Result<(), FifoError> {
+	syscall(SyscallCommand::FifoCloseWrite, &[key as u64]).map(|_| ()).map_err(fifo_error)
+}
+
+/// Let process `pid` use the FIFO, replacing whatever access it had. Only the owner can do this.
+/// With write access, `pid` is a writer until it calls `os_close_write`, even if it hasn't written anything yet.
+pub fn os_grant_fifo(key: FifoKey, pid: Pid, access: FifoAccess) -> Result<(), FifoError> {
+	syscall(SyscallCommand::FifoGrant, &[key as u64, pid, access as u64]).map(|_| ()).map_err(fifo_error)
+}
+
+/// Create a message queue holding up to `max_messages` messages of up to `max_len` bytes (at most `MAX_MESSAGE_LEN`,
+/// and `MAX_FIFO_CAPACITY` for all the messages together). Unlike a FIFO, each `os_send` is received whole by one `os_recv`.
+///
+/// It's a FIFO otherwise, with the same key space, owner and grants, so `os_grant_fifo`, `os_close_write` and
+/// `os_close_fifo` work on it too.
+pub fn os_init_message_queue(max_messages: usize, max_len: usize) -> Result<FifoKey, FifoError> {
+	syscall(SyscallCommand::InitMessageQueue, &[max_messages as u64, max_len as u64])
+		.map(|key| key as FifoKey)
+		.map_err(|err| match err {
+			SyscallError::InvalidArgument => FifoError::InvalidCapacity,
+			err => fifo_error(err),
+		})
+}
+
+fn send(key: FifoKey, message: &[u8], blocking: bool) -> Result<(), FifoError> {
+	if message.is_empty() {
+		return Err(FifoError::EmptyMessage);
+	}
+	fifo_write(key, message, blocking).map(|_| ())
+}
+
+/// Send `message` as one message, blocking while the queue is full.
+/// `MessageTooLong` if it's longer than the queue takes, empty messages aren't allowed since they'd look like the end.
+pub fn os_send(key: FifoKey, message: &[u8]) -> Result<(), FifoError> {
+	send(key, message, true)
+}
+
+/// `os_send` that returns `WouldBlock` if the queue is full
+pub fn os_try_send(key: FifoKey, message: &[u8]) -> Result<(), FifoError> {
+	send(key, message, false)
+}
+
+/// Receive the next message into `buf`, blocking until there is one. Returns its length,
+/// 0 meaning every sender has closed the queue and there is nothing left.
+///
+/// A message that doesn't fit in `buf` stays in the queue and returns `MessageTooLong`,
+/// a buffer of the queue's `max_len` always fits.
+pub fn os_recv(key: FifoKey, buf: &mut [u8]) -> Result<usize, FifoError> {
+	fifo_read(key, buf, true)
+}
+
+/// `os_recv` that returns `WouldBlock` if the queue is empty but still has senders
+pub fn os_try_recv(key: FifoKey, buf: &mut [u8]) -> Result<usize, FifoError> {
+	fifo_read(key, buf, false)
+}
+
+/// Give up the caller's process's access to the FIFO, which also closes its write end.
+/// The FIFO is removed once the owner and everything it was granted to has closed it or ended.
+pub fn os_close_fifo(key: FifoKey) -> Result<(), FifoError> {
+	syscall(SyscallCommand::FifoClose, &[key as u64]).map(|_| ()).map_err(fifo_error)
 }
 
 // Returns error if semaphore already exists
 pub fn os_init_sem(id: SemaphoreId, initial_count: i32) -> Result<(), ()> {
-	// TODO: A syscall probably should happen as normal processes wouldn't
-	// be allowed to directly lock the semaphore store
-	without_interrupts(|| {
-		let mut store =
-			SEMAPHORE_STORE.try_write().expect("DEADLOCK");
-		if store.contains_key(&id) {
-			Err(())
-		} else {
-			store.insert(id, Semaphore::new(initial_count));
-			Ok(())
-		}
-	})
+	syscall(SyscallCommand::InitSem, &[id as u64, initial_count as u32 as u64])
+		.map(|_| ())
+		.map_err(|_| ())
 }
 
 // Drop semaphore TODO: Added proper error type.
 pub fn os_drop_sem(id: SemaphoreId) -> Result<(), &'static str> {
-	without_interrupts(|| {
-		let mut store = SEMAPHORE_STORE.try_write().expect("DEADLOCK");
-		let out = store.remove(&id).ok_or("Semaphore Doesn't exist")?;
-		if out.is_neutral() {
-			Ok(())
-		} else {
-			eprintln!("{:?}", out);
-			Err("Semaphore is not neutral") // TODO: Make this a bit easier to debug
-		}
-	})
+	match syscall(SyscallCommand::DropSem, &[id as u64]) {
+		Ok(_) => Ok(()),
+		Err(SyscallError::Busy) => Err("Semaphore is not neutral"), // TODO: Make this a bit easier to debug
+		Err(_) => Err("Semaphore Doesn't exist"),
+	}
 }
 
+/// Acquire the semaphore, blocking the process until it's available
 pub fn os_wait(id: SemaphoreId) -> Result<(), ()> {
-	// TODO: Syscall, see init_sem
-	
-	without_interrupts(|| {
-		let store = SEMAPHORE_STORE.try_read().expect("DEADLOCK");
-		let entry = store.get(&id).ok_or(())?;
-		if entry.wait() { // Successfully acquired
-			Ok(())
-		} else { // Need to wait acquired
-			// TODO: There got to be a better solution than locking process manager to get the current pid
-			// Maybe have it atomically stored somewhere? What happens in multi-threaded applications?
-			// What we need is some way to identify who called us
-			// Check what page table / stack frame we are in?
-			// Again we have to reference the process manager to know what belongs to who
-			// !!!!
-			let pm = PROCESS_MANAGER.try_lock()
-				.expect("I just knew there was going to be a deadlock here");
-			
-			if pm.get_current_scheduling_level() == SchedulingLevel::Periodic {
-				// Only matters for periodic devices, as they are the only ones that can yield and then preempt
-				entry.add_to_wait_queue(pm.get_current_process_name());
-			}
-			
-			drop(pm);
-			drop(store);
-			
-			while {
-				enable_int();
-				os_yield();
-				disable_int();
-				let store =
-					SEMAPHORE_STORE.try_read().expect("DEADLOCK");
-				
-				// Since we can't delete semaphores, I just expect this to work
-				// Break the loop when we get the lock
-				!store.get(&id).unwrap().wait()
-			} {}
-			
-			Ok(())
-		}
-	})
+	// Only returns once we hold the semaphore, either straight away or after being handed it by os_signal
+	syscall(SyscallCommand::SemWait, &[id as u64]).map(|_| ()).map_err(|_| ())
 }
 
+/// Release the semaphore, if anything is waiting on it, exactly one waiter is made runnable
 pub fn os_signal(id: SemaphoreId) -> Result<(), ()> {
-	without_interrupts(|| {
-		let mut pm = PROCESS_MANAGER.try_lock().unwrap();
-		let store = SEMAPHORE_STORE.read();
-		let sem = store.get(&id).ok_or(())?;
-		sem.signal();
-		if sem.check_and_pop_if_exists(pm.get_scheduled_name()) {
-			pm.unyield_current_periodic();
-			drop(store);
-			drop(pm);
-			enable_int();
-			os_yield();
-			disable_int();
-		}
-		
-		Ok(())
-	})
+	// Might switch straight over to the woken up process, if it has higher priority
+	syscall(SyscallCommand::SemSignal, &[id as u64]).map(|_| ()).map_err(|_| ())
+}
+
+/// Log syscalls to the serial port, one line each, see `strace::trace` for the format.
+/// `TraceFilter::Pid` only logs the ones made by that process, `TraceFilter::Off` turns it back off.
+pub fn os_trace_syscalls(filter: TraceFilter) {
+	syscall(SyscallCommand::SetSyscallTrace, &filter.to_args()).expect("Failed to set the syscall trace");
 }
 
+/// Stop everything on this CPU. Only for the kernel itself (like the exception handlers), so not a syscall.
 pub fn os_abort() {
 	println!("!! OS TERMINATED !!");
 	interrupts::disable();
@@ -27,6 +27,9 @@ mod interrupts;
 mod gdt;
 mod helper;
 mod ipc;
+mod time;
+mod smp;
+mod acpi;
 
 // Logic
 mod kernel;
@@ -48,7 +51,7 @@ pub extern "C" fn _start(boot_info: &'static bootloader::BootInfo) -> ! {
 	let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
 	let mut mapper = unsafe { memory::paging::init(phys_mem_offset) };
 	unsafe { FRAME_ALLOCATOR.lock().init(&boot_info.memory_map, phys_mem_offset) };
-
+	
 	memory::allocator::init_heap(&mut mapper, &mut *FRAME_ALLOCATOR.lock())
 		.expect("Failed to init heap");
 	*TEMP_MAPPER.lock() = Some(mapper);
@@ -60,8 +63,7 @@ pub extern "C" fn _start(boot_info: &'static bootloader::BootInfo) -> ! {
 	
 	kernel::os_start();
 	println!("Didn't quite crash");
-	x86_64::instructions::interrupts::enable();
-	processes::idle_process();
+	processes::enter_idle_process(); // Enables interrupts
 }
 
 use core::panic::PanicInfo;
@@ -75,7 +77,7 @@ use x86_64::structures::paging::OffsetPageTable;
 fn panic(info: &PanicInfo) -> ! {
 	x86_64::instructions::interrupts::disable();
 	eprintln!("{}", info);
-
+	
 	loop {
 		x86_64::instructions::hlt();
 	}
@@ -95,4 +97,3 @@ fn panic(info: &PanicInfo) -> ! {
 fn alloc_error_handler(layout: alloc::alloc::Layout) -> ! {
 	panic!("allocation error: {:?}, probably not enough bytes", layout)
 }
-
@@ -4,13 +4,21 @@ use x86_64::{VirtAddr, PhysAddr};
 
 type PhyFrameIterator = impl Iterator<Item=PhysFrame>;
 
+/// Application processors start in real mode, so their startup code has to be in the first MiB
+const LOW_MEMORY_END: u64 = 0x10_0000;
+
 /// A FrameAllocator that returns usable frames from the bootloader's memory map.
 pub struct BootInfoFrameAllocator {
 	memory_map: Option<&'static MemoryMap>,
 	phys_frame_iter: Option<PhyFrameIterator>,
 	free_list_loc: u64,
 	free_list_length: usize,
+	/// Freed frames that have been zeroed since, handed out before anything else
+	zeroed_list_loc: u64,
+	zeroed_list_length: usize,
 	phys_memory_offset: VirtAddr,
+	/// A usable frame below `LOW_MEMORY_END`, kept out of normal allocation until someone takes it
+	low_frame: Option<PhysFrame>,
 }
 
 impl BootInfoFrameAllocator {
@@ -19,8 +27,11 @@ impl BootInfoFrameAllocator {
 			free_list_loc: 0,
 			phys_frame_iter: None,
 			free_list_length: 0,
+			zeroed_list_loc: 0,
+			zeroed_list_length: 0,
 			memory_map: None,
 			phys_memory_offset: VirtAddr::zero(),
+			low_frame: None,
 		}
 	}
 	
@@ -31,12 +42,14 @@ impl BootInfoFrameAllocator {
 	/// as `USABLE` in it are really unused.
 	pub unsafe fn init(&mut self, memory_map: &'static MemoryMap, physical_memory_offset: VirtAddr) {
 		self.phys_memory_offset = physical_memory_offset;
-		self.phys_frame_iter = Some(Self::usable_frames(memory_map));
+		self.low_frame = Self::usable_frames(memory_map, None)
+			.find(|frame| frame.start_address().as_u64() < LOW_MEMORY_END);
+		self.phys_frame_iter = Some(Self::usable_frames(memory_map, self.low_frame));
 		self.memory_map = Some(memory_map);
 	}
 	
-	/// Returns an iterator over the usable frames specified in the memory map.
-	fn usable_frames(memory_map: &'static MemoryMap) -> PhyFrameIterator {
+	/// Returns an iterator over the usable frames specified in the memory map, apart from `reserved`.
+	fn usable_frames(memory_map: &'static MemoryMap, reserved: Option<PhysFrame>) -> PhyFrameIterator {
 		let addr_ranges =
 			memory_map.iter()
 				.filter(|r| r.region_type == MemoryRegionType::Usable)
@@ -48,12 +61,48 @@ impl BootInfoFrameAllocator {
 		
 		// create `PhysFrame` types from the start addresses
 		frame_addresses.map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
+			.filter(move |&frame| Some(frame) != reserved)
+	}
+	
+	/// The frame below 1MiB that was put aside in `init`, None if there wasn't one or it was already taken
+	pub fn take_low_frame(&mut self) -> Option<PhysFrame> {
+		self.low_frame.take()
+	}
+	
+	/// Zero one freed frame and move it to the zeroed list, so freed memory (like old stacks) can't be snooped on.
+	/// Returns false if there was nothing left to zero.
+	pub fn zero_free_frame(&mut self) -> bool {
+		if self.free_list_length == 0 {
+			return false;
+		}
+		let frame_loc = self.free_list_loc;
+		let frame_ptr = (self.phys_memory_offset + frame_loc).as_mut_ptr::<u64>();
+		unsafe {
+			self.free_list_loc = *frame_ptr;
+			self.free_list_length -= 1;
+			
+			core::ptr::write_bytes(frame_ptr, 0, 4096 / 8);
+			// The first word is the link to the next zeroed frame, it's cleared again when the frame is handed out
+			*frame_ptr = self.zeroed_list_loc;
+		}
+		self.zeroed_list_loc = frame_loc;
+		self.zeroed_list_length += 1;
+		true
 	}
 }
 
 unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
 	fn allocate_frame(&mut self) -> Option<PhysFrame> {
-		if self.free_list_length > 0 {
+		if self.zeroed_list_length > 0 {
+			let ans = PhysFrame::containing_address(PhysAddr::new(self.zeroed_list_loc));
+			self.zeroed_list_length -= 1;
+			unsafe {
+				let frame_ptr = (self.phys_memory_offset + self.zeroed_list_loc).as_mut_ptr::<u64>();
+				self.zeroed_list_loc = *frame_ptr;
+				*frame_ptr = 0;
+			}
+			Some(ans)
+		} else if self.free_list_length > 0 {
 			let ans = PhysFrame::containing_address(PhysAddr::new(self.free_list_loc));
 			self.free_list_length -= 1;
 			unsafe {
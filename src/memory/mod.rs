@@ -1,9 +1,10 @@
-use x86_64::VirtAddr;
-use x86_64::structures::paging::{Page, Size4KiB, mapper, FrameAllocator, Mapper, FrameDeallocator};
+use x86_64::{VirtAddr, PhysAddr};
+use x86_64::structures::paging::{Page, PhysFrame, Size4KiB, mapper, FrameAllocator, Mapper, FrameDeallocator};
 use x86_64::structures::paging::page::PageRange;
 
 pub mod paging;
 pub mod allocator;
+pub mod user;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct StackBounds {
@@ -56,7 +57,7 @@ pub	fn dealloc_stack(
 			mapper.unmap(p).expect("Failed to unmap page");
 		flush.flush(); // Is this needed?
 		unsafe {
-			frame_deallocator.deallocate_frame(frame); // TODO: does nothing atm
+			frame_deallocator.deallocate_frame(frame); // Zeroed later by the idle process
 		}
 	}
 }
@@ -86,4 +87,46 @@ pub fn alloc_stack(
 		start: stack_start.start_address(),
 		end: stack_end.start_address(),
 	})
+}
+
+/// Map the page of memory mapped IO registers containing `phys_addr` into the kernel address space, with caching off.
+/// Returns the virtual address `phys_addr` ended up at.
+pub fn map_mmio(
+	phys_addr: PhysAddr,
+	mapper: &mut impl Mapper<Size4KiB>,
+	frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<VirtAddr, mapper::MapToError<Size4KiB>> {
+	use x86_64::structures::paging::PageTableFlags as Flags;
+	use core::sync::atomic::{AtomicU64, Ordering};
+	// Away from the stacks, and like them never given back
+	static MMIO_ALLOC_NEXT: AtomicU64 = AtomicU64::new(0x_4444_4444_0000);
+	
+	let page = Page::<Size4KiB>::from_start_address(VirtAddr::new(
+		MMIO_ALLOC_NEXT.fetch_add(Page::<Size4KiB>::SIZE, Ordering::Relaxed)))
+		.expect("`MMIO_ALLOC_NEXT` not page aligned");
+	let frame = PhysFrame::containing_address(phys_addr);
+	let flags = Flags::PRESENT | Flags::WRITABLE | Flags::NO_CACHE | Flags::WRITE_THROUGH;
+	unsafe {
+		mapper.map_to(page, frame, flags, frame_allocator)?.flush();
+	}
+	Ok(page.start_address() + (phys_addr.as_u64() - frame.start_address().as_u64()))
+}
+
+/// Map `frame` at the same virtual address as its physical address, for code that runs before paging is on
+pub fn identity_map(
+	frame: PhysFrame,
+	mapper: &mut impl Mapper<Size4KiB>,
+	frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), mapper::MapToError<Size4KiB>> {
+	use x86_64::structures::paging::PageTableFlags as Flags;
+	
+	match unsafe { mapper.identity_map(frame, Flags::PRESENT | Flags::WRITABLE, frame_allocator) } {
+		Ok(flush) => {
+			flush.flush();
+			Ok(())
+		}
+		// The bootloader might have mapped low memory already, which is fine
+		Err(mapper::MapToError::PageAlreadyMapped(mapped_frame)) if mapped_frame == frame => Ok(()),
+		Err(err) => Err(err),
+	}
 }
\ No newline at end of file
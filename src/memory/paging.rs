@@ -1,12 +1,23 @@
 use x86_64::{VirtAddr, PhysAddr};
-use x86_64::structures::paging::{PageTable, OffsetPageTable};
+use x86_64::structures::paging::{PageTable, OffsetPageTable, PageTableFlags};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Where the bootloader mapped all of physical memory, set by `init`
+static PHYSICAL_MEMORY_OFFSET: AtomicU64 = AtomicU64::new(0);
 
 /// Initialize a new OffsetPageTable.
 pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+	PHYSICAL_MEMORY_OFFSET.store(physical_memory_offset.as_u64(), Ordering::Relaxed);
 	let level_4_table = active_level_4_table(physical_memory_offset);
 	OffsetPageTable::new(level_4_table, physical_memory_offset)
 }
 
+/// Where `addr` can be read in the full physical memory mapping, for things the bootloader
+/// didn't map anywhere else (like the ACPI tables)
+pub fn phys_to_virt(addr: PhysAddr) -> VirtAddr {
+	VirtAddr::new(PHYSICAL_MEMORY_OFFSET.load(Ordering::Relaxed) + addr.as_u64())
+}
+
 /// Returns a mutable reference to the active level 4 table.
 unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut PageTable {
 	use x86_64::registers::control::Cr3;
@@ -20,6 +31,33 @@ unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut
 	&mut *page_table_ptr
 }
 
+/// Flags of the page `addr` is on, or None if it isn't mapped. WRITABLE and USER_ACCESSIBLE are only
+/// kept if every level of the page table allows them, since that's how the CPU sees them.
+pub fn effective_page_flags(addr: VirtAddr) -> Option<PageTableFlags> {
+	use x86_64::registers::control::Cr3;
+	let accumulated = PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE;
+	
+	let table_indexes = [
+		addr.p4_index(), addr.p3_index(), addr.p2_index(), addr.p1_index()
+	];
+	let mut table_addr = Cr3::read().0.start_address();
+	let mut allowed = accumulated;
+	for (level, &index) in table_indexes.iter().enumerate() {
+		let table = unsafe { &*phys_to_virt(table_addr).as_ptr::<PageTable>() };
+		let flags = table[index].flags();
+		if !flags.contains(PageTableFlags::PRESENT) {
+			return None;
+		}
+		allowed &= flags;
+		// A huge page ends the walk early
+		if level == table_indexes.len() - 1 || flags.contains(PageTableFlags::HUGE_PAGE) {
+			return Some((flags - accumulated) | allowed);
+		}
+		table_addr = table[index].addr();
+	}
+	unreachable!()
+}
+
 /// Translates the given virtual address to the mapped physical address, or
 /// `None` if the address is not mapped.
 pub unsafe fn _translate_addr(addr: VirtAddr, physical_memory_offset: VirtAddr) -> Option<PhysAddr> {
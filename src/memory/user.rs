@@ -0,0 +1,157 @@
+use alloc::vec::Vec;
+use core::mem::{size_of, MaybeUninit};
+use x86_64::VirtAddr;
+use x86_64::structures::idt::InterruptStackFrame;
+use x86_64::structures::paging::{Page, PageTableFlags, Size4KiB};
+use super::paging::effective_page_flags;
+
+global_asm!(include_str!("../user_copy.s"));
+
+extern "C" {
+	fn asm_copy_user(dest: *mut u8, src: *const u8, len: usize) -> usize;
+	static asm_copy_user_instruction: u8;
+	static asm_copy_user_fixup: u8;
+}
+
+/// A pointer from a process that the kernel can't use: not mapped, not writable when it has to be written to,
+/// or not user accessible when the caller is in user mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BadAddress;
+
+/// Types where any bytes are a valid value, so they can be copied in from a process without trusting it,
+/// and copied out without handing over anything but numbers.
+/// Anything with enums, references, bools or function pointers in it can't be one.
+pub unsafe trait PlainData: Copy {}
+
+unsafe impl PlainData for u8 {}
+unsafe impl PlainData for u64 {}
+unsafe impl PlainData for usize {}
+unsafe impl PlainData for i32 {}
+
+/// Check that the `len` bytes at `addr` are all mapped, and writable if `write`.
+/// Callers in user mode (`user_mode`) can only use user accessible pages, the kernel can use anything.
+pub fn check_range(addr: u64, len: usize, write: bool, user_mode: bool) -> Result<(), BadAddress> {
+	if len == 0 {
+		return Ok(());
+	}
+	if addr < Page::<Size4KiB>::SIZE {
+		return Err(BadAddress); // Null, even if the bootloader happened to map that page
+	}
+	let last = addr.checked_add(len as u64 - 1).ok_or(BadAddress)?;
+	let start = VirtAddr::try_new(addr).map_err(|_| BadAddress)?;
+	let end = VirtAddr::try_new(last).map_err(|_| BadAddress)?;
+	if (addr ^ last) & (1 << 47) != 0 {
+		return Err(BadAddress); // Goes through the non canonical hole
+	}
+	
+	let pages = Page::<Size4KiB>::range_inclusive(Page::containing_address(start), Page::containing_address(end));
+	for page in pages {
+		let flags = effective_page_flags(page.start_address()).ok_or(BadAddress)?;
+		if (write && !flags.contains(PageTableFlags::WRITABLE))
+			|| (user_mode && !flags.contains(PageTableFlags::USER_ACCESSIBLE)) {
+			return Err(BadAddress);
+		}
+	}
+	Ok(())
+}
+
+/// Copy `dest.len()` bytes from `src` in the caller's memory
+pub fn copy_from_user(dest: &mut [u8], src: u64, user_mode: bool) -> Result<(), BadAddress> {
+	check_range(src, dest.len(), false, user_mode)?;
+	// Still might fault, if another CPU unmaps it in the meantime
+	match unsafe { asm_copy_user(dest.as_mut_ptr(), src as *const u8, dest.len()) } {
+		0 => Ok(()),
+		_ => Err(BadAddress),
+	}
+}
+
+/// Copy `src` to `dest` in the caller's memory
+pub fn copy_to_user(dest: u64, src: &[u8], user_mode: bool) -> Result<(), BadAddress> {
+	check_range(dest, src.len(), true, user_mode)?;
+	match unsafe { asm_copy_user(dest as *mut u8, src.as_ptr(), src.len()) } {
+		0 => Ok(()),
+		_ => Err(BadAddress),
+	}
+}
+
+/// Read a `T` the caller passed a pointer to
+pub fn read_user<T: PlainData>(src: u64, user_mode: bool) -> Result<T, BadAddress> {
+	let mut value = MaybeUninit::<T>::uninit();
+	let bytes = unsafe { core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>()) };
+	copy_from_user(bytes, src, user_mode)?;
+	Ok(unsafe { value.assume_init() })
+}
+
+/// Read the `len` long array of `T` at `src`
+pub fn read_user_slice<T: PlainData>(src: u64, len: usize, user_mode: bool) -> Result<Vec<T>, BadAddress> {
+	let byte_len = len.checked_mul(size_of::<T>()).ok_or(BadAddress)?;
+	check_range(src, byte_len, false, user_mode)?;
+	let mut values = Vec::<T>::with_capacity(len);
+	let bytes = unsafe { core::slice::from_raw_parts_mut(values.as_mut_ptr() as *mut u8, byte_len) };
+	copy_from_user(bytes, src, user_mode)?;
+	unsafe { values.set_len(len) };
+	Ok(values)
+}
+
+/// Copy `value` into the caller's memory at `dest`. Only plain data, so nothing that points into the kernel
+/// (or owns kernel memory) ever makes it out.
+pub fn write_user<T: PlainData>(dest: u64, value: T, user_mode: bool) -> Result<(), BadAddress> {
+	write_user_slice(dest, core::slice::from_ref(&value), user_mode)
+}
+
+/// Copy `values` to the array at `dest` in the caller's memory
+pub fn write_user_slice<T: PlainData>(dest: u64, values: &[T], user_mode: bool) -> Result<(), BadAddress> {
+	let bytes = unsafe { core::slice::from_raw_parts(values.as_ptr() as *const u8, values.len() * size_of::<T>()) };
+	copy_to_user(dest, bytes, user_mode)
+}
+
+/// Called by the page fault handler, if the fault happened while copying to or from a process
+/// it makes the copy return instead. Returns false for every other fault.
+pub fn fixup_fault(stack_frame: &mut InterruptStackFrame) -> bool {
+	let copy_instruction = unsafe { &asm_copy_user_instruction as *const u8 as u64 };
+	if stack_frame.instruction_pointer.as_u64() != copy_instruction {
+		return false;
+	}
+	unsafe {
+		stack_frame.as_mut().instruction_pointer = VirtAddr::new(&asm_copy_user_fixup as *const u8 as u64);
+	}
+	true
+}
+
+#[cfg(test)]
+mod test {
+	use super::{check_range, copy_from_user, asm_copy_user, BadAddress};
+	use crate::{serial_print, serial_println};
+	
+	#[test_case]
+	fn test_check_user_range() {
+		serial_print!("test_check_user_range... ");
+		
+		let local = [1u8; 64];
+		let addr = local.as_ptr() as u64;
+		assert_eq!(check_range(addr, local.len(), true, false), Ok(()));
+		assert_eq!(check_range(0, 0, true, false), Ok(()));
+		assert_eq!(check_range(0, 1, false, false), Err(BadAddress));
+		assert_eq!(check_range(0x0000_8000_0000_0000, 1, false, false), Err(BadAddress)); // Non canonical
+		assert_eq!(check_range(u64::MAX, 2, false, false), Err(BadAddress)); // Wraps around
+		
+		let mut copy = [0u8; 64];
+		assert_eq!(copy_from_user(&mut copy, addr, false), Ok(()));
+		assert_eq!(copy, local);
+		
+		serial_println!("[ok]");
+	}
+	
+	#[test_case]
+	fn test_copy_user_fault() {
+		serial_print!("test_copy_user_fault... ");
+		
+		// Just below where the stacks start, so nothing is mapped there. The copy faults straight away
+		// and the page fault handler bails it out.
+		let mut buf = [0u8; 16];
+		let left = unsafe { asm_copy_user(buf.as_mut_ptr(), 0x_5555_5554_0000 as *const u8, buf.len()) };
+		assert_eq!(left, buf.len());
+		
+		serial_println!("[ok]");
+	}
+}
\ No newline at end of file
@@ -0,0 +1,152 @@
+use crate::processes::{Name, Pid, DeviceBudget, DeadlineParams};
+
+/// Highest share of the CPU (in thousandths) that device processes can reserve,
+/// the rest is kept for periodic and sporadic processes.
+pub const MAX_DEVICE_UTILIZATION_PERMILLE: usize = 700;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DeviceAdmissionError {
+	/// A device's name is its period, so it can't be 0
+	ZeroPeriod,
+	/// The device would use up its entire period, overlapping with itself every tick
+	BudgetExceedsPeriod { period: Name, budget: usize },
+	/// All the devices together would reserve more than `MAX_DEVICE_UTILIZATION_PERMILLE` of the CPU,
+	/// or leave too little for the deadline processes that have already been admitted
+	Overutilized { utilization_permille: usize },
+	/// When every device is released on the same tick, the device queue can't be cleared
+	/// before the device with the shortest period is released again
+	PeriodCollision { shortest_period: Name, total_budget: usize },
+	/// Devices would take up too much of a periodic frame, starving the periodic processes
+	FrameOverload { frame_demand: usize, frame_length: usize },
+	/// Tried to change the budget of a process that isn't a device
+	NotADevice(Pid),
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DeadlineAdmissionError {
+	ZeroPeriod,
+	/// The relative deadline has to be between 1 and the period
+	InvalidDeadline { relative_deadline: usize, period: usize },
+	/// The budget has to be between 1 and the relative deadline
+	InvalidBudget { budget: usize, relative_deadline: usize },
+	/// Devices and deadline processes together would need more than the whole CPU
+	Overutilized { utilization_permille: usize },
+}
+
+/// Check that the given set of (period, budget) devices can all be scheduled alongside
+/// a periodic frame of `frame_length` ticks.
+pub fn check_device_set(devices: &[(Name, DeviceBudget)], frame_length: usize) -> Result<(), DeviceAdmissionError> {
+	for &(period, budget) in devices {
+		if period == 0 {
+			return Err(DeviceAdmissionError::ZeroPeriod);
+		}
+		if budget.ticks >= period as usize {
+			return Err(DeviceAdmissionError::BudgetExceedsPeriod { period, budget: budget.ticks });
+		}
+	}
+	
+	// Round every share up, so we never underestimate
+	let utilization_permille: usize = devices.iter()
+		.map(|&(period, budget)| div_round_up(budget.ticks * 1000, period as usize))
+		.sum();
+	if utilization_permille > MAX_DEVICE_UTILIZATION_PERMILLE {
+		return Err(DeviceAdmissionError::Overutilized { utilization_permille });
+	}
+	
+	// Every period divides the lcm of all the periods, so at some point every device is released on the same tick
+	let total_budget: usize = devices.iter().map(|&(_, budget)| budget.ticks).sum();
+	if let Some(&(shortest_period, _)) = devices.iter().min_by_key(|&&(period, _)| period) {
+		if total_budget > shortest_period as usize {
+			return Err(DeviceAdmissionError::PeriodCollision { shortest_period, total_budget });
+		}
+	}
+	
+	// Worst case number of ticks the devices can steal from a single frame
+	let frame_demand: usize = devices.iter()
+		.map(|&(period, budget)| div_round_up(frame_length, period as usize) * budget.ticks)
+		.sum();
+	if frame_demand * 1000 > frame_length * MAX_DEVICE_UTILIZATION_PERMILLE {
+		return Err(DeviceAdmissionError::FrameOverload { frame_demand, frame_length });
+	}
+	
+	Ok(())
+}
+
+/// Check that the deadline processes can all meet their deadlines under EDF,
+/// in whatever time is left after the devices (which always go first).
+/// The periodic frame isn't counted: deadline processes go before it, so its slots keep counting down while they run
+/// and a periodic process can lose part (or all) of its slot. Periodic slots only keep their guarantee without any
+/// deadline processes.
+pub fn check_deadline_set(devices: &[(Name, DeviceBudget)], deadlines: &[DeadlineParams]) -> Result<(), DeadlineAdmissionError> {
+	for params in deadlines {
+		if params.period == 0 {
+			return Err(DeadlineAdmissionError::ZeroPeriod);
+		}
+		if params.relative_deadline == 0 || params.relative_deadline > params.period {
+			return Err(DeadlineAdmissionError::InvalidDeadline {
+				relative_deadline: params.relative_deadline,
+				period: params.period,
+			});
+		}
+		if params.budget == 0 || params.budget > params.relative_deadline {
+			return Err(DeadlineAdmissionError::InvalidBudget {
+				budget: params.budget,
+				relative_deadline: params.relative_deadline,
+			});
+		}
+	}
+	
+	// Density instead of utilization, since deadlines can be shorter than the period
+	let utilization_permille: usize = devices.iter()
+		.map(|&(period, budget)| div_round_up(budget.ticks * 1000, period as usize))
+		.chain(deadlines.iter().map(|params| params.density_permille()))
+		.sum();
+	if utilization_permille > 1000 {
+		return Err(DeadlineAdmissionError::Overutilized { utilization_permille });
+	}
+	
+	Ok(())
+}
+
+fn div_round_up(a: usize, b: usize) -> usize {
+	(a + b - 1) / b
+}
+
+#[cfg(test)]
+mod test {
+	use super::{check_device_set, check_deadline_set, DeviceAdmissionError, DeadlineAdmissionError};
+	use crate::processes::{DeviceBudget, DeadlineParams};
+	use crate::{serial_print, serial_println};
+	
+	#[test_case]
+	fn test_device_admission() {
+		serial_print!("test_device_admission... ");
+		
+		let budget = DeviceBudget::DEFAULT;
+		assert_eq!(check_device_set(&[(10, budget), (15, budget)], 37), Ok(()));
+		assert_eq!(check_device_set(&[(0, budget)], 37), Err(DeviceAdmissionError::ZeroPeriod));
+		assert_eq!(check_device_set(&[(1, budget)], 37),
+				   Err(DeviceAdmissionError::BudgetExceedsPeriod { period: 1, budget: 1 }));
+		assert_eq!(check_device_set(&[(2, budget), (3, budget)], 37),
+				   Err(DeviceAdmissionError::Overutilized { utilization_permille: 834 }));
+		assert_eq!(check_device_set(&[(4, budget), (20, budget), (20, budget), (20, budget), (20, budget)], 37),
+				   Err(DeviceAdmissionError::PeriodCollision { shortest_period: 4, total_budget: 5 }));
+		
+		serial_println!("[ok]");
+	}
+	
+	#[test_case]
+	fn test_deadline_admission() {
+		serial_print!("test_deadline_admission... ");
+		
+		let budget = DeviceBudget::DEFAULT;
+		let params = DeadlineParams { period: 10, relative_deadline: 5, budget: 2 };
+		assert_eq!(check_deadline_set(&[(10, budget)], &[params]), Ok(()));
+		assert_eq!(check_deadline_set(&[(2, budget)], &[params, params]),
+				   Err(DeadlineAdmissionError::Overutilized { utilization_permille: 1300 }));
+		assert_eq!(check_deadline_set(&[], &[DeadlineParams { relative_deadline: 11, ..params }]),
+				   Err(DeadlineAdmissionError::InvalidDeadline { relative_deadline: 11, period: 10 }));
+		
+		serial_println!("[ok]");
+	}
+}
\ No newline at end of file
@@ -0,0 +1,148 @@
+/// Timing of a `Deadline` process, all in ticks
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(C)]
+pub struct DeadlineParams {
+	/// A new job is released every `period` ticks
+	pub period: usize,
+	/// Each job has to finish (by yielding) within this many ticks of being released, can't be longer than the period
+	pub relative_deadline: usize,
+	/// Ticks a job can run for before its deadline gets pushed back
+	pub budget: usize,
+}
+
+impl DeadlineParams {
+	/// Share of the CPU (in thousandths) needed to always meet the deadlines, rounded up
+	pub fn density_permille(&self) -> usize {
+		(self.budget * 1000 + self.relative_deadline - 1) / self.relative_deadline
+	}
+}
+
+/// Constant bandwidth server for a deadline process.
+///
+/// Processes are picked by the earliest `server_deadline`. When a job runs out of budget,
+/// the budget is refilled but the server deadline moves back by a period,
+/// so an overrunning job can't take more than its share from the other deadline processes.
+#[derive(Copy, Clone, Debug)]
+pub struct DeadlineServer {
+	params: DeadlineParams,
+	/// Deadline used for EDF ordering, pushed back by a period each time the budget runs out
+	server_deadline: usize,
+	/// Real deadline of the current job, used to count misses
+	job_deadline: usize,
+	next_release: usize,
+	budget_left: usize,
+	/// A job has been released and hasn't finished yet
+	active: bool,
+	/// The current job has already been counted as missed
+	missed: bool,
+	misses: usize,
+}
+
+impl DeadlineServer {
+	/// The first job is released straight away
+	pub fn new(params: DeadlineParams, time: usize) -> Self {
+		let mut server = DeadlineServer {
+			params,
+			server_deadline: 0,
+			job_deadline: 0,
+			next_release: time,
+			budget_left: 0,
+			active: false,
+			missed: false,
+			misses: 0,
+		};
+		server.release(time);
+		server
+	}
+	
+	pub fn get_params(&self) -> DeadlineParams {
+		self.params
+	}
+	
+	pub fn get_server_deadline(&self) -> usize {
+		self.server_deadline
+	}
+	
+	pub fn get_misses(&self) -> usize {
+		self.misses
+	}
+	
+	/// Has a job that still needs to run
+	pub fn is_active(&self) -> bool {
+		self.active
+	}
+	
+	/// Release the next job if its period has come around.
+	/// A job that is still running at that point carries on as the new job.
+	pub fn release(&mut self, time: usize) {
+		if time < self.next_release {
+			return;
+		}
+		self.next_release += self.params.period;
+		self.job_deadline = time + self.params.relative_deadline;
+		self.server_deadline = self.job_deadline;
+		self.budget_left = self.params.budget;
+		self.active = true;
+		self.missed = false;
+	}
+	
+	/// Count one more tick against the budget, returns true if it ran out and the server deadline was pushed back
+	pub fn charge_tick(&mut self) -> bool {
+		self.budget_left -= 1;
+		if self.budget_left == 0 {
+			self.budget_left = self.params.budget;
+			self.server_deadline += self.params.period;
+			true
+		} else {
+			false
+		}
+	}
+	
+	/// The current job is done, nothing to run until the next release
+	pub fn complete(&mut self) {
+		self.active = false;
+	}
+	
+	/// Returns true the first time the current job is seen to be past its deadline
+	pub fn check_miss(&mut self, time: usize) -> bool {
+		if self.active && !self.missed && time >= self.job_deadline {
+			self.missed = true;
+			self.misses += 1;
+			true
+		} else {
+			false
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{DeadlineServer, DeadlineParams};
+	use crate::{serial_print, serial_println};
+	
+	#[test_case]
+	fn test_deadline_server() {
+		serial_print!("test_deadline_server... ");
+		
+		let params = DeadlineParams { period: 10, relative_deadline: 5, budget: 2 };
+		assert_eq!(params.density_permille(), 400);
+		let mut server = DeadlineServer::new(params, 0);
+		assert!(server.is_active());
+		assert_eq!(server.get_server_deadline(), 5);
+		assert!(!server.charge_tick());
+		assert!(server.charge_tick());
+		assert_eq!(server.get_server_deadline(), 15); // Postponed, but the job still has to finish by 5
+		assert!(!server.check_miss(4));
+		assert!(server.check_miss(5));
+		assert!(!server.check_miss(6)); // Only counted once per job
+		server.release(9);
+		assert_eq!(server.get_server_deadline(), 15);
+		server.release(10);
+		assert_eq!(server.get_server_deadline(), 15);
+		server.complete();
+		assert!(!server.check_miss(15));
+		assert_eq!(server.get_misses(), 1);
+		
+		serial_println!("[ok]");
+	}
+}
\ No newline at end of file
@@ -0,0 +1,520 @@
+use alloc::vec::Vec;
+use alloc::vec;
+#[allow(unused_imports)]
+use crate::{eprintln, println};
+use crate::processes::{Pid, Name, SchedulingLevel, SporadicPriority, CreateProcessError, SPORADIC_PRIORITY_LEVELS,
+					   DeadlineParams};
+use crate::processes::process::{Process, ProcessStatus, DeviceBudget, DeviceOverrunPolicy};
+use crate::processes::table::ProcessTable;
+use crate::processes::policy::{SchedulingPolicy, TickOutcome};
+use crate::processes::scheduling::{Scheduler, PeriodicOrderError};
+use crate::processes::admission::{self, DeviceAdmissionError, DeadlineAdmissionError};
+use crate::processes::sporadic_queue::{LOWEST_SPORADIC_PRIORITY, LEVEL_ALLOTMENT, AGING_TICKS};
+use crate::special_collections::DynamicBitmap;
+use crate::time::ms_to_ticks;
+use crate::smp::BOOT_CPU;
+
+/// Devices first (released every `name` ticks), then deadline processes by earliest deadline,
+/// then the periodic process of the current PPP slot, then the sporadic multi level feedback queue,
+/// and idle if there is nothing else.
+pub struct DefaultPolicy {
+	scheduler: Scheduler,
+	name_registry: DynamicBitmap,
+}
+
+impl DefaultPolicy {
+	pub fn new() -> Self {
+		Self {
+			scheduler: Scheduler::new(vec![(1, ms_to_ticks(100)), (2, ms_to_ticks(50)), (3, ms_to_ticks(50)),
+										   (1, ms_to_ticks(50)), (2, ms_to_ticks(100)), (4, ms_to_ticks(20))]),
+			name_registry: DynamicBitmap::new(),
+		}
+	}
+	
+	/// Replace the periodic order, taking effect at the next frame boundary (or immediately if nothing has run yet).
+	///
+	/// Every currently existing periodic process must have a slot in the new order.
+	pub fn set_periodic_order(&mut self, table: &ProcessTable, periodic_order: Vec<(Name, usize)>) -> Result<(), PeriodicOrderError> {
+		Scheduler::validate_periodic_order(&periodic_order)?;
+		if let Some(unused) = table.iter()
+			.filter(|c| c.get_process_scheduling_level() == SchedulingLevel::Periodic)
+			.map(|c| c.get_name())
+			.find(|&name| !periodic_order.iter().any(|&(inside_name, _)| inside_name == name)) {
+			return Err(PeriodicOrderError::UnusedName(unused));
+		}
+		
+		self.scheduler.set_periodic_order(periodic_order);
+		Ok(())
+	}
+	
+	/// Change the budget of a device, as long as the devices are still schedulable afterwards
+	pub fn set_device_budget(&self, table: &mut ProcessTable, pid: Pid, budget: DeviceBudget) -> Result<(), DeviceAdmissionError> {
+		let name = table.get_process_with_pid(pid)
+			.filter(|c| c.get_process_scheduling_level() == SchedulingLevel::Device)
+			.ok_or(DeviceAdmissionError::NotADevice(pid))?
+			.get_name();
+		let mut devices = Self::device_budgets(table, Some(pid));
+		devices.push((name, budget));
+		admission::check_device_set(&devices, self.periodic_frame_length())?;
+		Self::check_devices_leave_room_for_deadlines(table, &devices)?;
+		
+		table.get_process_mut_with_pid(pid).unwrap().set_device_budget(budget);
+		Ok(())
+	}
+	
+	/// (Period, Budget) of every device, except for `skip_pid`
+	fn device_budgets(table: &ProcessTable, skip_pid: Option<Pid>) -> Vec<(Name, DeviceBudget)> {
+		table.iter()
+			.filter(|c| c.get_process_scheduling_level() == SchedulingLevel::Device)
+			.filter(|c| Some(c.get_pid()) != skip_pid)
+			.map(|c| (c.get_name(), c.get_device_budget()))
+			.collect()
+	}
+	
+	fn deadline_params(table: &ProcessTable) -> Vec<DeadlineParams> {
+		table.iter()
+			.filter_map(|c| c.get_deadline_server())
+			.map(|c| c.get_params())
+			.collect()
+	}
+	
+	/// Devices always go before deadline processes, so a device change can break deadlines that were fine before
+	fn check_devices_leave_room_for_deadlines(table: &ProcessTable, devices: &[(Name, DeviceBudget)])
+											  -> Result<(), DeviceAdmissionError> {
+		match admission::check_deadline_set(devices, &Self::deadline_params(table)) {
+			Err(DeadlineAdmissionError::Overutilized { utilization_permille }) =>
+				Err(DeviceAdmissionError::Overutilized { utilization_permille }),
+			_ => Ok(()), // The deadline processes themselves were already checked when they were admitted
+		}
+	}
+	
+	/// Release new deadline jobs, and report the jobs that are past their deadline
+	fn update_deadline_servers(table: &mut ProcessTable, time: usize) {
+		for process in table.iter_mut() {
+			let pid = process.get_pid();
+			let name = process.get_name();
+			if let Some(server) = process.get_deadline_server_mut() {
+				if server.check_miss(time) {
+					eprintln!("DEADLINE PROCESS {} (name {}) MISSED ITS DEADLINE, {} MISSES SO FAR", pid, name, server.get_misses());
+				}
+				server.release(time);
+			}
+		}
+	}
+	
+	/// Charge a tick to the current deadline process, pushing its deadline back if it has used up its budget
+	fn charge_current_deadline(table: &mut ProcessTable) {
+		table.get_current_process_mut()
+			.get_deadline_server_mut()
+			.expect("Deadline process without a deadline server")
+			.charge_tick();
+	}
+	
+	fn periodic_frame_length(&self) -> usize {
+		self.scheduler.effective_periodic_order().iter().map(|&(_, time)| time).sum()
+	}
+	
+	/// Charge a tick to the current sporadic process. It's demoted once it has used up the allotment of its level,
+	/// otherwise it goes to the back of its level once its quantum runs out.
+	fn charge_current_sporadic(&mut self, table: &mut ProcessTable) {
+		let time = self.scheduler.time;
+		let current_process = table.get_current_process_mut();
+		current_process.set_last_ran(time);
+		let pid = current_process.get_pid();
+		let priority = current_process.get_sporadic_priority();
+		let quantum_expired = current_process.charge_quantum_tick();
+		
+		if priority != LOWEST_SPORADIC_PRIORITY && current_process.charge_sporadic_tick() >= LEVEL_ALLOTMENT[priority] {
+			current_process.set_sporadic_priority(priority + 1);
+			current_process.reset_quantum();
+			self.scheduler.sporadic_queue.change_priority(pid, priority + 1);
+		} else if quantum_expired {
+			current_process.reset_quantum();
+			self.scheduler.sporadic_queue.requeue(pid);
+		}
+	}
+	
+	/// Move sporadic processes that have been waiting for too long up a level, so they don't starve
+	fn age_sporadic_processes(&mut self, table: &mut ProcessTable) {
+		let time = self.scheduler.time;
+		let current_pid = table.get_current_pid();
+		let starved = self.scheduler.sporadic_queue.agable_pids()
+			.filter(|&pid| pid != current_pid && !table.is_running_elsewhere(pid))
+			.filter(|&pid| table.get_process_with_pid(pid)
+				.map(|c| time - c.get_last_ran() >= AGING_TICKS)
+				.unwrap_or(false))
+			.collect::<Vec<Pid>>();
+		
+		for pid in starved {
+			let process = table.get_process_mut_with_pid(pid).expect("Pid in sporadic queue doesn't exist");
+			let new_priority = process.get_sporadic_priority() - 1;
+			process.set_sporadic_priority(new_priority);
+			process.set_last_ran(time); // Start waiting again from the new level
+			self.scheduler.sporadic_queue.change_priority(pid, new_priority);
+		}
+	}
+	
+	/// Apply the overrun policy of the currently running device, which has just used up its budget
+	fn handle_device_overrun(&mut self, table: &mut ProcessTable) -> TickOutcome {
+		let current_process = table.get_current_process_mut();
+		let pid = current_process.get_pid();
+		let policy = current_process.get_device_budget().policy;
+		eprintln!("DEVICE {} (name {}) WENT OVER ITS BUDGET: {:?}", pid, current_process.get_name(), policy);
+		
+		match policy {
+			DeviceOverrunPolicy::Log => {}
+			DeviceOverrunPolicy::Preempt => {
+				current_process.reset_device_ticks();
+				current_process.set_process_status(ProcessStatus::Scheduled);
+				assert_eq!(self.scheduler.device_queue.pop_front(), Some(pid),
+						   "Currently executing device not in the front of device queue?");
+				self.schedule(table, true);
+			}
+			DeviceOverrunPolicy::Demote => {
+				current_process.reset_device_ticks();
+				current_process.set_process_status(ProcessStatus::Scheduled);
+				current_process.set_process_scheduling_level(SchedulingLevel::Sporadic);
+				current_process.set_sporadic_priority(LOWEST_SPORADIC_PRIORITY);
+				self.scheduler.device_queue.retain(|&c| c != pid);
+				self.scheduler.sporadic_queue.push_back(pid, LOWEST_SPORADIC_PRIORITY);
+				self.schedule(table, true);
+			}
+			DeviceOverrunPolicy::Kill => {
+				return TickOutcome::TerminateCurrent;
+			}
+		}
+		TickOutcome::Continue
+	}
+	
+	fn switch_to_device(&mut self, table: &mut ProcessTable) -> bool {
+		if let Some(&new_pid) = self.scheduler.device_queue.front() {
+			assert!(table.get_process_with_pid(new_pid).is_some(),
+					"No entry with pid in process list, PID from device queue, possibly caused by duplicate entries");
+			table.switch_to(new_pid);
+			true
+		} else {
+			false
+		}
+	}
+	
+	/// The deadline process with a job to run and the earliest server deadline, ties go to the lowest pid
+	fn switch_to_deadline(&mut self, table: &mut ProcessTable) -> bool {
+		let next = table.iter()
+			.filter(|c| !c.get_process_status().is_suspended())
+			.filter_map(|c| c.get_deadline_server()
+				.filter(|server| server.is_active())
+				.map(|server| (server.get_server_deadline(), c.get_pid())))
+			.min();
+		if let Some((_, new_pid)) = next {
+			table.switch_to(new_pid);
+			true
+		} else {
+			false
+		}
+	}
+	
+	/// Move on to the next periodic slot if the current one is over, returns the name that should be running,
+	/// or None if the process of the current slot has yielded it.
+	///
+	/// This has to happen on every tick that isn't taken by a device, even if a deadline process ends up running.
+	fn advance_periodic(&mut self, table: &mut ProcessTable) -> Option<Name> {
+		let old_name = self.scheduler.get_current_periodic_entry().0;
+		let (name, next_periodic) = self.scheduler.check_and_change_periodic();
+		if next_periodic {
+			// Whatever yielded in the slot that just ended gets to run again in its next slot.
+			// Just because next_periodic is true doesn't mean the name changed, which is fine.
+			table.iter_mut()
+				.filter(|c| c.get_process_scheduling_level() == SchedulingLevel::Periodic && c.get_name() == old_name)
+				.filter(|c| c.get_process_status() == ProcessStatus::Yielded)
+				.for_each(|c| c.set_process_status(ProcessStatus::Scheduled));
+		}
+		if self.scheduler.periodic_yielded {
+			return None;
+		}
+		Some(name)
+	}
+	
+	/// Switch to the process with `name` or one of its threads, which all share the slot.
+	/// Returns false if none of them can run right now.
+	fn switch_to_periodic(&mut self, table: &mut ProcessTable, name: Name) -> bool {
+		let current_process = table.get_current_process();
+		
+		// Basically if there is nothing that needs changing, return true.
+		if current_process.get_process_scheduling_level() == SchedulingLevel::Periodic &&
+			current_process.get_name() == name &&
+			current_process.get_process_status() == ProcessStatus::Running {
+			return true;
+		}
+		
+		// Otherwise, figure out the replacement, skipping whatever yielded or is suspended
+		if !self.name_registry.check_bit(name as usize) {
+			return false;
+		}
+		let new_pid = table.iter()
+			.filter(|c| c.get_process_scheduling_level() == SchedulingLevel::Periodic && c.get_name() == name)
+			.find(|c| c.get_process_status() == ProcessStatus::Scheduled)
+			.map(|c| c.get_pid());
+		if let Some(new_pid) = new_pid {
+			table.switch_to(new_pid);
+			true
+		} else {
+			false // Leave the time slot to the lower levels
+		}
+	}
+	
+	fn switch_to_sporadic(&mut self, table: &mut ProcessTable) -> bool {
+		// Skip over the ones other CPUs are already running
+		let next_pid = self.scheduler.sporadic_queue.iter()
+			.find(|&pid| !table.is_running_elsewhere(pid));
+		if let Some(new_pid) = next_pid {
+			assert!(table.get_process_with_pid(new_pid).is_some(),
+					"No entry with pid in process list, PID from sporadic queue, possibly caused by duplicate entries");
+			table.switch_to(new_pid);
+			true
+		} else {
+			false // No entries in sporadic queue
+		}
+	}
+}
+
+impl SchedulingPolicy for DefaultPolicy {
+	fn admit(&self, table: &ProcessTable, level: SchedulingLevel, name: Name, priority: SporadicPriority,
+			 deadline: Option<DeadlineParams>) -> Result<(), CreateProcessError> {
+		match level {
+			SchedulingLevel::Device => self.admit_device(table, name, DeviceBudget::DEFAULT),
+			SchedulingLevel::Deadline => {
+				let params = deadline.ok_or(CreateProcessError::MissingDeadlineParams)?;
+				let mut deadlines = Self::deadline_params(table);
+				deadlines.push(params);
+				admission::check_deadline_set(&Self::device_budgets(table, None), &deadlines)
+					.map_err(CreateProcessError::DeadlineUnschedulable)
+			}
+			SchedulingLevel::Periodic => {
+				// Check against the order that will actually be used, so a pending order counts too
+				if !self.scheduler.effective_periodic_order().iter().any(|&(inside_name, _)| inside_name == name) {
+					return Err(CreateProcessError::UnknownPeriodicName(name));
+				}
+				if self.name_registry.check_bit(name as usize) { // If the name has already been taken
+					return Err(CreateProcessError::NameTaken(name));
+				}
+				Ok(())
+			}
+			SchedulingLevel::Sporadic => {
+				if priority >= SPORADIC_PRIORITY_LEVELS {
+					return Err(CreateProcessError::InvalidPriority(priority));
+				}
+				Ok(())
+			}
+			SchedulingLevel::Idle => panic!("You can't just go create a Idle process"),
+		}
+	}
+	
+	fn admit_device(&self, table: &ProcessTable, name: Name, budget: DeviceBudget) -> Result<(), CreateProcessError> {
+		let mut devices = Self::device_budgets(table, None);
+		devices.push((name, budget));
+		admission::check_device_set(&devices, self.periodic_frame_length())
+			.and_then(|_| Self::check_devices_leave_room_for_deadlines(table, &devices))
+			.map_err(CreateProcessError::DeviceUnschedulable)
+	}
+	
+	fn admit_thread(&self, table: &ProcessTable, owner: &Process) -> Result<(), CreateProcessError> {
+		match owner.get_process_scheduling_level() {
+			SchedulingLevel::Periodic => Ok(()), // Takes turns with the rest of the process in its slot
+			level => self.admit(table, level, owner.get_name(), owner.get_sporadic_priority(),
+								owner.get_deadline_server().map(|c| c.get_params())),
+		}
+	}
+	
+	fn process_created(&mut self, table: &mut ProcessTable, pid: Pid) {
+		let process = table.get_process_with_pid(pid).expect("Created process isn't in the table");
+		match process.get_process_scheduling_level() {
+			SchedulingLevel::Periodic => {
+				self.name_registry.set_bit(process.get_name() as usize);
+			}
+			SchedulingLevel::Sporadic => {
+				self.scheduler.sporadic_queue.push_back(pid, process.get_sporadic_priority());
+			}
+			_ => {} // Devices are added to the device queue when they are released
+		}
+	}
+	
+	fn process_ended(&mut self, table: &mut ProcessTable, process: &Process) {
+		let pid = process.get_pid();
+		let is_current = pid == table.get_current_pid();
+		match process.get_process_scheduling_level() {
+			SchedulingLevel::Device => {
+				if is_current { // Check if things still align with mental model
+					assert_eq!(self.scheduler.device_queue.pop_front(), Some(pid),
+							   "Currently executing device not in the front of device queue?");
+				}
+				// We need the following in both cases because there might be multiple instances
+				// of the device in the device queue
+				self.scheduler.device_queue.retain(|&c| c != pid);
+			}
+			SchedulingLevel::Periodic => {
+				// The name stays taken while any thread of the process is still around
+				let name = process.get_name();
+				if !table.iter().any(|c| c.get_process_scheduling_level() == SchedulingLevel::Periodic && c.get_name() == name) {
+					self.name_registry.clear_bit(name as usize);
+				}
+			}
+			SchedulingLevel::Sporadic => {
+				let removed = self.scheduler.sporadic_queue.remove(pid);
+				if is_current {  // Check if things still align with mental model
+					assert!(removed, "Currently executing sporadic not in the sporadic queue?");
+				}
+			}
+			SchedulingLevel::Deadline => {} // Picked straight from the process table
+			SchedulingLevel::Idle => panic!("The idle processes can't just end!?")
+		}
+	}
+	
+	fn yield_current(&mut self, table: &mut ProcessTable) {
+		let current_process = table.get_current_process_mut();
+		let current_pid = current_process.get_pid();
+		
+		match current_process.get_process_scheduling_level() {
+			SchedulingLevel::Device => {
+				current_process.reset_device_ticks();
+				current_process.set_process_status(ProcessStatus::Scheduled);
+				assert_eq!(self.scheduler.device_queue.pop_front(), Some(current_pid),
+						   "Currently executing device not in the front of device queue?");
+				self.schedule(table, false);
+			}
+			SchedulingLevel::Periodic => {
+				current_process.set_process_status(ProcessStatus::Yielded);
+				let name = current_process.get_name();
+				// The rest of the slot goes to the other threads of the process first
+				if !self.switch_to_periodic(table, name) {
+					self.scheduler.periodic_yielded = true;
+					if !self.switch_to_deadline(table) && !self.switch_to_sporadic(table) {
+						table.switch_to_idle();
+					}
+				}
+			}
+			SchedulingLevel::Deadline => {
+				current_process.get_deadline_server_mut()
+					.expect("Deadline process without a deadline server")
+					.complete();
+				current_process.set_process_status(ProcessStatus::Scheduled);
+				self.schedule(table, false);
+			}
+			SchedulingLevel::Sporadic => {
+				current_process.set_process_status(ProcessStatus::Scheduled);
+				current_process.reset_quantum();
+				self.scheduler.sporadic_queue.requeue(current_pid);
+				// Sporadic processes don't really yield if there is nothing else running
+				self.switch_to_sporadic(table);
+			}
+			SchedulingLevel::Idle => panic!("Why is the idle process yielding?!?!")
+		}
+	}
+	
+	fn suspend_current(&mut self, table: &mut ProcessTable) {
+		let current_process = table.get_current_process_mut();
+		let current_pid = current_process.get_pid();
+		
+		match current_process.get_process_scheduling_level() {
+			SchedulingLevel::Device => {
+				current_process.reset_device_ticks();
+				assert_eq!(self.scheduler.device_queue.pop_front(), Some(current_pid),
+						   "Currently executing device not in the front of device queue?");
+			}
+			SchedulingLevel::Periodic => {} // switch_to_periodic skips suspended processes
+			SchedulingLevel::Deadline => {} // The job carries on when it is resumed, switch_to_deadline skips it until then
+			SchedulingLevel::Sporadic => {
+				assert!(self.scheduler.sporadic_queue.remove(current_pid),
+						"Currently executing sporadic not in the sporadic queue?");
+			}
+			SchedulingLevel::Idle => panic!("The idle process can't be suspended")
+		}
+	}
+	
+	fn resume(&mut self, table: &mut ProcessTable, pid: Pid) {
+		let process = table.get_process_with_pid(pid).expect("Resumed process doesn't exist");
+		if process.get_process_scheduling_level() == SchedulingLevel::Sporadic {
+			self.scheduler.sporadic_queue.push_back(pid, process.get_sporadic_priority());
+		}
+	}
+	
+	fn tick(&mut self, table: &mut ProcessTable, time: usize) -> TickOutcome {
+		if table.get_cpu() != BOOT_CPU {
+			// The other CPUs only run sporadic processes, all the timekeeping is done on the boot CPU
+			if table.get_current_process().get_process_scheduling_level() == SchedulingLevel::Sporadic {
+				self.charge_current_sporadic(table);
+			}
+			self.schedule(table, true);
+			return TickOutcome::Continue;
+		}
+		let previous_time = self.scheduler.time as u64;
+		self.scheduler.time = time;
+		let current_time = time as u64;
+		// A period started since the last tick, normally exactly at this one. A device that is still queued (or
+		// running, it's at the front) from its last period isn't queued again, it just carries on.
+		let released = table.iter()
+			.filter(|c| c.get_process_scheduling_level() == SchedulingLevel::Device)
+			.filter(|c| !c.get_process_status().is_suspended())
+			.filter(|c| current_time / c.get_name() != previous_time / c.get_name())
+			.map(|c| c.get_pid())
+			.filter(|pid| !self.scheduler.device_queue.contains(pid))
+			.collect::<Vec<Pid>>();
+		self.scheduler.device_queue.extend(released);
+		Self::update_deadline_servers(table, time);
+		
+		let current_process = table.get_current_process_mut();
+		match current_process.get_process_scheduling_level() {
+			SchedulingLevel::Device => {
+				if current_process.charge_device_tick() {
+					return self.handle_device_overrun(table);
+				}
+			}
+			SchedulingLevel::Sporadic => {
+				self.charge_current_sporadic(table);
+				self.age_sporadic_processes(table);
+				self.schedule(table, true)
+			}
+			SchedulingLevel::Deadline => {
+				Self::charge_current_deadline(table);
+				self.age_sporadic_processes(table);
+				self.schedule(table, true)
+			}
+			SchedulingLevel::Periodic | SchedulingLevel::Idle => {
+				self.age_sporadic_processes(table);
+				self.schedule(table, true)
+			}
+		}
+		TickOutcome::Continue
+	}
+	
+	fn schedule(&mut self, table: &mut ProcessTable, tick: bool) {
+		if table.get_cpu() != BOOT_CPU {
+			// Devices, deadline and periodic processes keep to the boot CPU, so their timing stays the same as
+			// on one CPU. The rest of the CPUs share the sporadic queue.
+			if !self.switch_to_sporadic(table) {
+				table.switch_to_idle();
+			}
+			return;
+		}
+		let current_process = table.get_current_process();
+		// Devices are at the front of the device queue until they finish, so nothing can preempt them
+		if current_process.get_process_scheduling_level() == SchedulingLevel::Device &&
+			current_process.get_process_status() == ProcessStatus::Running {
+			return;
+		}
+		
+		if !self.switch_to_device(table) { // If no device is scheduled.
+			if tick {
+				self.scheduler.periodic_time -= 1;
+			}
+			let periodic_name = self.advance_periodic(table);
+			if !self.switch_to_deadline(table) { // If no deadline job is waiting
+				// If no process scheduled for the next time slot
+				if !periodic_name.map(|name| self.switch_to_periodic(table, name)).unwrap_or(false) {
+					if !self.switch_to_sporadic(table) { // No processes in sporadic queue either
+						table.switch_to_idle();
+					}
+				}
+			}
+		}
+	}
+}
\ No newline at end of file
@@ -0,0 +1,57 @@
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr4, Cr4Flags};
+
+/// Default x87 control word, all exceptions masked, 64 bit precision
+const DEFAULT_FCW: u16 = 0x037F;
+/// Default SSE control/status, all exceptions masked, round to nearest
+const DEFAULT_MXCSR: u32 = 0x1F80;
+
+/// Let processes use the x87 FPU and SSE.
+///
+/// AVX is never turned on (XCR0 is left alone), so the 512 byte FXSAVE area holds
+/// everything a process can change. The kernel itself is compiled with soft float,
+/// so the registers only ever hold the state of the current process.
+pub fn fpu_init() {
+	unsafe {
+		Cr0::update(|flags| {
+			flags.remove(Cr0Flags::EMULATE_COPROCESSOR | Cr0Flags::TASK_SWITCHED);
+			flags.insert(Cr0Flags::MONITOR_COPROCESSOR);
+		});
+		Cr4::update(|flags| flags.insert(Cr4Flags::OSFXSR | Cr4Flags::OSXMMEXCPT_ENABLE));
+		llvm_asm!("fninit" :::: "volatile");
+	}
+}
+
+/// FPU and SSE registers of a process, in the FXSAVE layout
+#[derive(Clone)]
+#[repr(C, align(16))]
+pub struct FpuState([u8; 512]);
+
+impl FpuState {
+	/// What the registers look like after `fninit`, with the default MXCSR
+	pub fn new() -> FpuState {
+		let mut state = FpuState([0; 512]);
+		state.0[0..2].copy_from_slice(&DEFAULT_FCW.to_le_bytes());
+		state.0[24..28].copy_from_slice(&DEFAULT_MXCSR.to_le_bytes());
+		state
+	}
+	
+	/// Store the current registers in here
+	pub fn save(&mut self) {
+		unsafe {
+			llvm_asm!("fxsave64 ($0)" : : "r"(self.0.as_mut_ptr()) : "memory" : "volatile");
+		}
+	}
+	
+	/// Load the registers from here
+	pub fn restore(&self) {
+		unsafe {
+			llvm_asm!("fxrstor64 ($0)" : : "r"(self.0.as_ptr()) : "memory" : "volatile");
+		}
+	}
+}
+
+impl core::fmt::Debug for FpuState {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.write_str("FpuState { .. }")
+	}
+}
\ No newline at end of file
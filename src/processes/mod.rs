@@ -1,16 +1,32 @@
 mod scheduling;
+mod admission;
+mod sporadic_queue;
+mod deadline;
+pub mod fpu;
+mod table;
+mod policy;
+mod default_policy;
 pub mod process;
 
-pub use process::{Process};
+pub use process::{Process, ProcessInfo, DeviceBudget, DeviceOverrunPolicy, CpuUsage};
 use spin::Mutex;
 use alloc::vec::Vec;
-use alloc::vec;
+use alloc::collections::{BTreeSet, BTreeMap};
 use lazy_static::lazy_static;
+use num_enum::TryFromPrimitive;
 #[allow(unused_imports)]
 use crate::{eprintln, println};
-use crate::processes::scheduling::Scheduler;
-use crate::special_collections::{IncrementingPool, DynamicBitmap};
+pub use crate::processes::scheduling::PeriodicOrderError;
+pub use crate::processes::admission::{DeviceAdmissionError, DeadlineAdmissionError};
+pub use crate::processes::deadline::DeadlineParams;
+use crate::processes::deadline::DeadlineServer;
+pub use crate::processes::sporadic_queue::{SporadicPriority, SPORADIC_PRIORITY_LEVELS, DEFAULT_SPORADIC_PRIORITY};
+pub use crate::processes::table::ProcessTable;
+pub use crate::processes::policy::{SchedulingPolicy, TickOutcome};
+pub use crate::processes::default_policy::DefaultPolicy;
+use crate::special_collections::IncrementingPool;
 use crate::processes::process::ProcessStatus;
+use crate::smp::{MAX_CPUS, BOOT_CPU};
 use x86_64::VirtAddr;
 
 lazy_static! {
@@ -19,116 +35,292 @@ lazy_static! {
 
 pub type Name = u64;
 pub type Pid = u64;
+/// Threads are scheduled like processes, so their ids come from the same pool
+pub type Tid = Pid;
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u64)]
 pub enum SchedulingLevel {
 	Device = 0,
 	Periodic = 1,
 	Sporadic = 2,
 	Idle = 3,
+	/// Dispatched by earliest deadline first, see `DeadlineParams`
+	Deadline = 4,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CreateProcessError {
+	/// Another periodic process already has this name
+	NameTaken(Name),
+	/// Periodic name doesn't appear in the periodic order, so it could never be executed
+	UnknownPeriodicName(Name),
+	/// Adding the device would make the system unschedulable
+	DeviceUnschedulable(DeviceAdmissionError),
+	/// Sporadic priority has to be below `SPORADIC_PRIORITY_LEVELS`
+	InvalidPriority(SporadicPriority),
+	/// Adding the deadline process would make some deadlines impossible to meet
+	DeadlineUnschedulable(DeadlineAdmissionError),
+	/// Deadline processes have to be created with `DeadlineParams`
+	MissingDeadlineParams,
 }
 
-pub struct ProcessesManager {
-	processes_list: Vec<Option<Process>>,
-	idle_process: Process,
-	scheduler: Scheduler,
-	currently_executing_process: Pid,
+pub struct ProcessesManager<P: SchedulingPolicy = DefaultPolicy> {
+	table: ProcessTable,
+	policy: P,
+	/// Ticks since the scheduler started, `time::ticks()` as of the last tick the boot CPU handled
+	time: usize,
+	/// (Wake up time, Pid), ordered so the earliest wake up is first
+	sleep_queue: BTreeSet<(usize, Pid)>,
+	/// Thread -> processes blocked in `join_thread` until it ends
+	join_waiters: BTreeMap<Tid, Vec<Pid>>,
 	pid_pool: IncrementingPool,
-	name_registry: DynamicBitmap,
+	/// Per CPU, TSC time (ns) from which the running time is charged to the current process
+	slice_start_ns: [u64; MAX_CPUS],
+	/// Processes that were ended while running on another CPU, which still has to switch away from them
+	pending_ends: BTreeSet<Pid>,
 }
 
-impl ProcessesManager {
+impl ProcessesManager<DefaultPolicy> {
 	pub fn new() -> Self {
+		Self::with_policy(DefaultPolicy::new())
+	}
+	
+	/// Replace the periodic order, taking effect at the next frame boundary (or immediately if nothing has run yet).
+	///
+	/// Every currently existing periodic process must have a slot in the new order.
+	pub fn set_periodic_order(&mut self, periodic_order: Vec<(Name, usize)>) -> Result<(), PeriodicOrderError> {
+		self.policy.set_periodic_order(&self.table, periodic_order)
+	}
+	
+	/// Change the budget of a device, as long as the devices are still schedulable afterwards
+	pub fn set_device_budget(&mut self, pid: Pid, budget: DeviceBudget) -> Result<(), DeviceAdmissionError> {
+		self.policy.set_device_budget(&mut self.table, pid, budget)
+	}
+}
+
+impl<P: SchedulingPolicy> ProcessesManager<P> {
+	pub fn with_policy(policy: P) -> Self {
 		Self {
-			processes_list: vec![None; 2],
-			currently_executing_process: 0,
-			idle_process: Process::idle(),
-			scheduler: Scheduler::new(vec![(1, 10), (2, 5), (3, 5), (1, 5), (2, 10), (4, 2)]),
+			table: ProcessTable::new(),
+			policy,
+			time: 0,
+			sleep_queue: BTreeSet::new(),
+			join_waiters: BTreeMap::new(),
 			pid_pool: IncrementingPool::new(1),
-			name_registry: DynamicBitmap::new(),
+			slice_start_ns: [crate::time::time_ns(); MAX_CPUS],
+			pending_ends: BTreeSet::new(),
 		}
 	}
 	
-	pub fn create_new_process(&mut self, level: SchedulingLevel, name: Name, arg: i32, program_start: extern "C" fn()) -> Result<(), ()> {
-		match level {
-			SchedulingLevel::Device => {}
-			SchedulingLevel::Periodic => {
-				if !self.name_registry.set_bit(name as usize) { return Err(()); } // If the name has already been taken
-				// And panic if the name doesn't exist in the order, since then the process can never be executed
-				assert!(self.scheduler.periodic_order.iter().any(|&(inside_name, _)| inside_name == name),
-						"Name not inside Periodic order")
-			}
-			SchedulingLevel::Sporadic => {}
-			SchedulingLevel::Idle => panic!("You can't just go create a Idle process"),
-		}
-		let process = Process::new(self.pid_pool.get_free_elem(), level, name, arg, program_start);
+	/// Create the idle process of this CPU, which runs whenever the policy has nothing else for the CPU to run.
+	/// It becomes the current process, but only starts running once `start_idle_process` is called.
+	pub fn create_idle_process(&mut self) -> Pid {
+		let process = Process::new(self.pid_pool.get_free_elem(), SchedulingLevel::Idle, 0, 0, idle_process);
+		let pid = process.get_pid();
+		self.table.insert(process);
+		self.table.set_idle(pid);
+		pid
+	}
+	
+	/// Stack pointer to jump to, to start running the idle process for the first time
+	pub fn start_idle_process(&mut self) -> VirtAddr {
+		assert_ne!(self.table.get_idle_pid(), 0, "Idle process hasn't been created");
+		self.slice_start_ns[self.table.get_cpu()] = crate::time::time_ns();
+		self.table.get_current_process().get_stack_pos()
+	}
+	
+	/// `priority` is only used by sporadic processes, and `deadline` by deadline processes
+	pub fn create_new_process(&mut self, level: SchedulingLevel, name: Name, arg: i32, priority: SporadicPriority,
+							  deadline: Option<DeadlineParams>, program_start: extern "C" fn()) -> Result<Pid, CreateProcessError> {
+		self.policy.admit(&self.table, level, name, priority, deadline)?;
+		
+		let process = self.build_process(level, name, arg, priority, deadline, program_start);
+		Ok(self.add_process(process))
+	}
+	
+	/// Create a device that runs with `budget` from the start, instead of the default budget.
+	/// It's admitted with that budget, and its overrun policy applies from its very first release.
+	pub fn create_device(&mut self, name: Name, arg: i32, budget: DeviceBudget, program_start: extern "C" fn())
+		-> Result<Pid, CreateProcessError> {
+		self.policy.admit_device(&self.table, name, budget)?;
 		
-		if process.get_idx() >= self.processes_list.len() {
-			self.processes_list.resize(process.get_idx() + 1, None);
+		let mut process = self.build_process(SchedulingLevel::Device, name, arg, DEFAULT_SPORADIC_PRIORITY, None,
+											 program_start);
+		process.set_device_budget(budget);
+		Ok(self.add_process(process))
+	}
+	
+	/// Start a thread of the current process running `thread_start`, with its own stack and `arg` as its parameter.
+	///
+	/// The thread gets the level, name, sporadic priority and deadline parameters of the process it belongs to,
+	/// and is scheduled as if it was another process of that kind. Threads spawned by threads belong to the same process.
+	pub fn spawn_thread(&mut self, arg: i32, thread_start: extern "C" fn()) -> Result<Tid, CreateProcessError> {
+		let owner_pid = self.table.get_current_process().get_owner_pid();
+		let owner = self.table.get_process_with_pid(owner_pid).expect("Thread outlived its process");
+		self.policy.admit_thread(&self.table, owner)?;
+		
+		let level = owner.get_process_scheduling_level();
+		let name = owner.get_name();
+		let priority = owner.get_sporadic_priority();
+		let deadline = owner.get_deadline_server().map(|c| c.get_params());
+		let mut thread = self.build_process(level, name, arg, priority, deadline, thread_start);
+		thread.set_owner(owner_pid);
+		Ok(self.add_process(thread))
+	}
+	
+	fn build_process(&mut self, level: SchedulingLevel, name: Name, arg: i32, priority: SporadicPriority,
+					 deadline: Option<DeadlineParams>, program_start: extern "C" fn()) -> Process {
+		let mut process = Process::new(self.pid_pool.get_free_elem(), level, name, arg, program_start);
+		process.set_sporadic_priority(priority);
+		process.set_last_ran(self.time);
+		if let Some(params) = deadline {
+			process.set_deadline_server(DeadlineServer::new(params, self.time));
 		}
+		process
+	}
+	
+	fn add_process(&mut self, process: Process) -> Pid {
 		let out_pid = process.get_pid();
-		if level == SchedulingLevel::Sporadic {
-			self.scheduler.sporadic_queue.push_back(out_pid);
+		self.table.insert(process);
+		self.policy.process_created(&mut self.table, out_pid);
+		out_pid
+	}
+	
+	/// `tid` is one of the other threads of the process the current process belongs to
+	pub fn can_join(&self, tid: Tid) -> bool {
+		let current_process = self.table.get_current_process();
+		tid != current_process.get_pid() && self.table.get_process_with_pid(tid)
+			.map(|c| c.get_owner() == Some(current_process.get_owner_pid()))
+			.unwrap_or(false)
+	}
+	
+	/// Block the current process until thread `tid` has ended, returns the stack pointer of whatever should run next.
+	/// Carries straight on if there is nothing to wait for.
+	pub fn join_thread(&mut self, stack_p: VirtAddr, tid: Tid) -> VirtAddr {
+		if !self.can_join(tid) {
+			return stack_p; // Ended before we got here
 		}
+		let current_pid = self.table.get_current_pid();
+		self.join_waiters.entry(tid).or_insert_with(Vec::new).push(current_pid);
+		self.block_current_process(stack_p)
+	}
+	
+	pub fn yield_current_process(&mut self, stack_p: VirtAddr) -> VirtAddr {
+		let current_process = self.table.get_current_process_mut();
+		current_process.set_stack_pos(stack_p);
+		current_process.get_usage_mut().yields += 1;
+		self.policy.yield_current(&mut self.table);
 		
-		assert!(self.processes_list[process.get_idx()].is_none(), "PID of new process is not empty");
-		let idx = process.get_idx();
-		self.processes_list[idx] = Some(process);
+		self.table.get_current_process().get_stack_pos()
+	}
+	
+	/// Put the current process to sleep until the scheduler time reaches `wake_time`,
+	/// returns the stack pointer of whatever should run next.
+	pub fn sleep_current_process(&mut self, stack_p: VirtAddr, wake_time: usize) -> VirtAddr {
+		if wake_time <= self.time {
+			return stack_p; // Already past the wake up time, just carry on
+		}
 		
-		Ok(())
+		let current_pid = self.suspend_current_process(stack_p, ProcessStatus::Sleeping);
+		self.sleep_queue.insert((wake_time, current_pid));
+		self.policy.schedule(&mut self.table, false);
+		
+		self.table.get_current_process().get_stack_pos()
 	}
 	
-	pub fn yield_current_process(&mut self, stack_p: VirtAddr) -> VirtAddr {
-		let current_process = self.get_current_process_mut();
+	/// Block the current process until `unblock_process` is called with its pid,
+	/// returns the stack pointer of whatever should run next.
+	pub fn block_current_process(&mut self, stack_p: VirtAddr) -> VirtAddr {
+		self.suspend_current_process(stack_p, ProcessStatus::Blocked);
+		self.policy.schedule(&mut self.table, false);
+		
+		self.table.get_current_process().get_stack_pos()
+	}
+	
+	/// Make a blocked process runnable again, and switch to it straight away if the policy
+	/// prefers it over the current process. Returns the stack pointer of whatever should run next.
+	pub fn unblock_process(&mut self, stack_p: VirtAddr, pid: Pid) -> VirtAddr {
+		self.unblock_processes(stack_p, &[pid])
+	}
+	
+	/// `unblock_process` for all of `pids` at once, only scheduling once they are all runnable.
+	/// Nothing changes if `pids` is empty.
+	pub fn unblock_processes(&mut self, stack_p: VirtAddr, pids: &[Pid]) -> VirtAddr {
+		if pids.is_empty() {
+			return stack_p;
+		}
+		self.table.get_current_process_mut().set_stack_pos(stack_p);
+		for &pid in pids {
+			assert_eq!(self.table.get_process_with_pid(pid).map(|c| c.get_process_status()), Some(ProcessStatus::Blocked),
+					   "Unblocking a process that isn't blocked");
+			self.resume_process(pid);
+		}
+		let current_pid = self.table.get_current_pid();
+		self.policy.schedule(&mut self.table, false);
+		self.count_preemption(current_pid);
+		
+		self.table.get_current_process().get_stack_pos()
+	}
+	
+	/// Take the current process off the run queues, marking it with `status`.
+	/// It won't be scheduled again until `resume_process` is called.
+	fn suspend_current_process(&mut self, stack_p: VirtAddr, status: ProcessStatus) -> Pid {
+		assert!(status.is_suspended(), "Suspending with a runnable status");
+		let current_process = self.table.get_current_process_mut();
 		current_process.set_stack_pos(stack_p);
+		current_process.set_process_status(status);
+		current_process.reset_quantum();
 		let current_pid = current_process.get_pid();
 		
-		match current_process.get_process_scheduling_level() {
-			SchedulingLevel::Device => {
-				assert_eq!(self.scheduler.device_queue.pop_front(), Some(current_pid),
-						   "Currently executing device not in the front of device queue?");
-				self.schedule_all_the_stuff(false);
-			}
-			SchedulingLevel::Periodic => {
-				current_process.set_process_status(ProcessStatus::Yielded);
-				self.scheduler.periodic_yielded = true;
-				if !self.switch_to_sporadic() {
-					self.switch_to_idle(false);
-				}
-			}
-			SchedulingLevel::Sporadic => {
-				current_process.set_process_status(ProcessStatus::Scheduled);
-				self.scheduler.sporadic_queue.rotate_left(1);
-				// Sporadic processes don't really yield if there is nothing else running
-				self.switch_to_sporadic();
+		self.policy.suspend_current(&mut self.table);
+		current_pid
+	}
+	
+	/// Put a suspended process back into the run queues
+	fn resume_process(&mut self, pid: Pid) {
+		let process = self.table.get_process_mut_with_pid(pid)
+			.expect("Suspended process doesn't exist, it should have been removed from its queue");
+		process.set_process_status(ProcessStatus::Scheduled);
+		process.set_last_ran(self.time); // Don't count time spent suspended towards aging
+		self.policy.resume(&mut self.table, pid);
+	}
+	
+	/// Move every process whose wake up time has passed out of the sleep queue
+	fn wake_sleeping_processes(&mut self) {
+		let current_time = self.time;
+		while let Some(&(wake_time, pid)) = self.sleep_queue.iter().next() {
+			if wake_time > current_time {
+				break;
 			}
-			SchedulingLevel::Idle => panic!("Why is the idle process yielding?!?!")
+			self.sleep_queue.remove(&(wake_time, pid));
+			self.resume_process(pid);
 		}
-		
-		self.get_current_process().get_stack_pos()
 	}
 	
 	pub fn get_current_process_arg(&self) -> i32 {
-		self.get_current_process().get_arg()
+		self.table.get_current_process().get_arg()
 	}
 	
 	pub fn end_current_process(&mut self) -> VirtAddr {
 		// TODO: Maybe wipe the stack to prevent other processes from snooping on the memory?
 		
-		self.end_process_with_pid(self.currently_executing_process);
+		self.end_process_with_pid(self.table.get_current_pid());
 		
 		// Technically we won't be running the idle function, just looping in terminate. But that's fine for now
-		self.switch_to_idle(false); // Ehh... maybe do something so the next task can immediately pick up before timer tick
-		self.schedule_all_the_stuff(true);
-		self.get_current_process().get_stack_pos()
+		self.table.switch_to_idle_unchecked(); // Ehh... maybe do something so the next task can immediately pick up before timer tick
+		self.policy.schedule(&mut self.table, true);
+		self.table.get_current_process().get_stack_pos()
 	}
 	
+	/// Ends every process except the current one, and the rest of the process it belongs to
 	pub fn end_all_other_processes(&mut self) {
-		let pids = self.processes_list
+		let current_owner = self.table.get_current_process().get_owner_pid();
+		let pids = self.table
 			.iter()
-			.filter_map(|c| c.as_ref())
+			.filter(|c| c.get_process_scheduling_level() != SchedulingLevel::Idle)
+			.filter(|c| c.get_owner_pid() != current_owner)
 			.map(|c| c.get_pid())
-			.filter(|c| *c != self.currently_executing_process)
 			.collect::<Vec<Pid>>();
 		
 		for pid in pids {
@@ -138,35 +330,54 @@ impl ProcessesManager {
 	
 	// TODO: add error type
 	fn end_process_with_pid(&mut self, pid: Pid) -> Result<(), ()> {
-		let target_process =
-			self.processes_list[pid as usize - 1].take().ok_or(())?;
+		if self.table.is_running_elsewhere(pid) {
+			// Its CPU is still on its stack, so it's ended once that CPU switches away (see `end_pending_processes`)
+			self.pending_ends.insert(pid);
+			return Ok(());
+		}
+		let target_process = self.table.take(pid).ok_or(())?;
+		println!("Process {} (name {}) ended: {:?}", pid, target_process.get_name(), target_process.get_usage());
 		
 		crate::memory::dealloc_stack(target_process.get_stack_bounds(),
 									 &mut *crate::TEMP_MAPPER.lock().as_mut().unwrap(),
 									 &mut *crate::FRAME_ALLOCATOR.lock());
 		
-		match target_process.get_process_scheduling_level() {
-			SchedulingLevel::Device => {
-				if pid == self.currently_executing_process { // Check if things still align with mental model
-					assert_eq!(self.scheduler.device_queue.pop_front(), Some(target_process.get_pid()),
-							   "Currently executing device not in the front of device queue?");
-				}
-				// We need the following in both cases because there might be multiple instances
-				// of the device in the device queue
-				self.scheduler.device_queue.retain(|&c| c != target_process.get_pid());
+		self.policy.process_ended(&mut self.table, &target_process);
+		if target_process.get_process_status() == ProcessStatus::Sleeping {
+			let sleep_entry = self.sleep_queue.iter()
+				.find(|&&(_, c)| c == pid)
+				.copied();
+			if let Some(sleep_entry) = sleep_entry {
+				self.sleep_queue.remove(&sleep_entry);
 			}
-			SchedulingLevel::Periodic => {
-				self.name_registry.clear_bit(target_process.get_name() as usize);
+		} else if target_process.get_process_status() == ProcessStatus::Blocked {
+			for sem in crate::sync::SEMAPHORE_STORE.read().values() {
+				sem.remove_from_wait_queue(pid);
+			}
+			for waiters in self.join_waiters.values_mut() {
+				waiters.retain(|&c| c != pid);
+			}
+			crate::ipc::remove_waiter(pid);
+		}
+		
+		if let Some(waiters) = self.join_waiters.remove(&pid) {
+			for waiter in waiters {
+				self.resume_process(waiter);
+			}
+		}
+		if target_process.get_owner().is_none() {
+			// Closes its FIFOs, the ones nobody else uses are gone. Waiting readers get to see the end.
+			for waiter in crate::ipc::process_ended(pid) {
+				self.resume_process(waiter);
 			}
-			SchedulingLevel::Sporadic => {
-				if pid == self.currently_executing_process {  // Check if things still align with mental model
-					assert_eq!(self.scheduler.sporadic_queue.pop_front(), Some(target_process.get_pid()),
-							   "Currently executing sporadic not in the front of sporadic queue?");
-				} else {
-					self.scheduler.sporadic_queue.retain(|c| *c != pid);
-				}
+			// Threads don't outlive their process
+			let threads = self.table.iter()
+				.filter(|c| c.get_owner() == Some(pid))
+				.map(|c| c.get_pid())
+				.collect::<Vec<Tid>>();
+			for tid in threads {
+				self.end_process_with_pid(tid);
 			}
-			SchedulingLevel::Idle => panic!("The idle processes can't just end!?")
 		}
 		self.pid_pool.return_elem(target_process.get_pid());
 		
@@ -176,200 +387,172 @@ impl ProcessesManager {
 	
 	/// Return None when it wants to just continue with whatever we are doing
 	pub fn next_tick_preempt_process(&mut self, stack_p: usize) -> Option<VirtAddr> {
-		self.scheduler.time += 1;
-		let current_time = self.scheduler.time as u64;
-		self.scheduler.device_queue.extend(self.processes_list.iter()
-			.filter_map(|c| c.as_ref())
-			.filter(|c| c.get_process_scheduling_level() == SchedulingLevel::Device)
-			.filter(|c| current_time % c.get_name() == 0)
-			.map(|c| c.get_pid()));
+		// Every CPU ticks, but only the boot CPU keeps time. It catches up with the ticks counted so far, in case
+		// a tick ever went by without it getting here.
+		if self.table.get_cpu() == BOOT_CPU {
+			self.time = crate::time::ticks();
+			self.wake_sleeping_processes();
+		}
+		self.charge_user_time();
 		
-		let current_process = self.get_current_process_mut();
+		let current_process = self.table.get_current_process_mut();
+		current_process.get_usage_mut().ticks += 1;
 		assert_eq!(current_process.get_process_status(), ProcessStatus::Running, "Currently running process {} is not running??", current_process.get_arg());
 		// Save the new stack position even if we don't need to change (because it's easier this way)
 		current_process.set_stack_pos(VirtAddr::new(stack_p as u64));
-		match current_process.get_process_scheduling_level() {
-			SchedulingLevel::Device => {
-				eprintln!("DEVICE TAKING MORE THAN 1 TICK TO COMPLETE!");
+		let current_pid = current_process.get_pid();
+		match self.policy.tick(&mut self.table, self.time) {
+			TickOutcome::Continue => self.count_preemption(current_pid),
+			TickOutcome::TerminateCurrent => {
+				self.end_current_process();
 			}
-			SchedulingLevel::Periodic | SchedulingLevel::Sporadic | SchedulingLevel::Idle =>
-				self.schedule_all_the_stuff(true)
 		}
+		self.end_pending_processes();
+		// Don't charge the scheduling itself to anyone
+		self.slice_start_ns[self.table.get_cpu()] = crate::time::time_ns();
+		self.switch_fpu_state(current_pid);
 		
-		Some(self.get_current_process().get_stack_pos())
+		Some(self.table.get_current_process().get_stack_pos())
 	}
 	
-	fn schedule_all_the_stuff(&mut self, tick: bool) {
-		if !self.switch_to_device() { // If no device is scheduled.
-			if tick {
-				self.scheduler.periodic_time -= 1;
-			}
-			if !self.switch_to_periodic() { // If no process scheduled for the next time slot
-				if !self.switch_to_sporadic() { // No processes in sporadic queue either
-					self.switch_to_idle(true);
-				}
-			}
+	/// End the processes that were ended while running on another CPU, if they have stopped running since
+	fn end_pending_processes(&mut self) {
+		if self.pending_ends.remove(&self.table.get_current_pid()) {
+			self.end_current_process();
+		}
+		let stopped = self.pending_ends.iter()
+			.filter(|&&pid| !self.table.is_running_elsewhere(pid))
+			.copied()
+			.collect::<Vec<Pid>>();
+		for pid in stopped {
+			self.pending_ends.remove(&pid);
+			self.end_process_with_pid(pid);
 		}
 	}
 	
-	fn switch_to_idle(&mut self, set_scheduled: bool) {
-		if set_scheduled {
-			let current_process = self.get_current_process_mut();
-			if current_process.get_process_status() == ProcessStatus::Running {
-				current_process.set_process_status(ProcessStatus::Scheduled);
-			}
-		}
-		self.currently_executing_process = 0; // Idle
-		self.idle_process.set_process_status(ProcessStatus::Running);
-	}
-	
-	fn switch_to_device(&mut self) -> bool {
-		let old_process = self.currently_executing_process;
-		if let Some(new_process) = self.scheduler.device_queue.front()
-			.map(|&c| c) // Copy the value early to workaround borrow checker limitation
-			.map(|new_pid| self.get_process_mut_with_pid(new_pid)
-				.expect("No entry with pid in process list, PID from device queue, possibly caused by duplicate entries")) {
-			new_process.set_process_status(ProcessStatus::Running);
-			// Have to do this to get around borrow checker
-			self.currently_executing_process = new_process.get_pid();
-			assert_ne!(old_process, self.currently_executing_process);
-			self.get_process_mut_with_pid(old_process)
-				.expect("Old process doesn't exist")
-				.set_process_status(ProcessStatus::Scheduled);
-			true
-		} else {
-			false
-		}
+	/// Charge the time since the last context switch or syscall to the current process,
+	/// called whenever we come into the kernel. Saturating here and in `leave_kernel`, so a TSC that disagrees with
+	/// the one the slice started on charges nothing instead of wrapping around.
+	fn charge_user_time(&mut self) {
+		let now = crate::time::time_ns();
+		let cpu = self.table.get_cpu();
+		self.table.get_current_process_mut().get_usage_mut().user_ns += now.saturating_sub(self.slice_start_ns[cpu]);
+		self.slice_start_ns[cpu] = now;
 	}
 	
-	fn switch_to_periodic(&mut self) -> bool {
-		// self.get_current_process_mut().unwrap().set_process_status(ProcessStatus::Scheduled);
-		
-		let previously_yielded = self.scheduler.periodic_yielded;
-		let old_name = self.scheduler.get_current_periodic_entry().0;
-		let (name, _next_periodic) = self.scheduler.check_and_change_periodic();
-		if self.scheduler.periodic_yielded {
-			return false;
-		}
-		
-		if previously_yielded { // If the previous
-			let yielded_process = self.get_process_with_name(old_name, true)
-				.expect("Yielded process not found");
-			assert_eq!(yielded_process.get_process_status(), ProcessStatus::Yielded,
-					   "Previously yielded but not yielded process status, this might be correct (but rare) behaviour though");
-			yielded_process.set_process_status(ProcessStatus::Scheduled);
+	/// Count it as a preemption if `old_pid` was switched out while it could still run
+	fn count_preemption(&mut self, old_pid: Pid) {
+		if self.table.get_current_pid() == old_pid {
+			return;
 		}
-		
-		// Just because _next_periodic is true doesn't mean process changed.
-		
-		let current_process = self.get_current_process_mut();
-		
-		// Basically if there is nothing that needs changing, return true.
-		if current_process.get_process_scheduling_level() == SchedulingLevel::Periodic &&
-			current_process.get_name() == name {
-			return current_process.get_process_status() != ProcessStatus::Yielded; // Return false if yielded, but moved to next periodic?, let sporadic schedule
+		if let Some(old_process) = self.table.get_process_mut_with_pid(old_pid) {
+			if old_process.get_process_status() == ProcessStatus::Scheduled {
+				old_process.get_usage_mut().preemptions += 1;
+			}
 		}
-		
-		// Otherwise, figure out the replacement
-		if self.name_registry.check_bit(name as usize) {
-			self.get_current_process_mut().set_process_status(ProcessStatus::Scheduled);
-			let new_process = self.get_process_with_name(name, true).expect("Process not found");
-			
-			assert_eq!(new_process.get_process_status(), ProcessStatus::Scheduled,
-					   "New process was already running: {}", new_process.get_name());
-			new_process.set_process_status(ProcessStatus::Running);
-			self.currently_executing_process = new_process.get_pid();
-			true
-		} else {
-			false
+	}
+	
+	/// Called at the start of every syscall, returns the pid of the caller to pass to `leave_kernel`
+	pub fn enter_kernel(&mut self) -> Pid {
+		self.charge_user_time();
+		self.table.get_current_pid()
+	}
+	
+	/// Called at the end of every syscall, charging the time spent in it to the caller (if it still exists),
+	/// even if the syscall switched to a different process
+	pub fn leave_kernel(&mut self, caller_pid: Pid) {
+		let now = crate::time::time_ns();
+		let cpu = self.table.get_cpu();
+		if let Some(caller) = self.table.get_process_mut_with_pid(caller_pid) {
+			caller.get_usage_mut().kernel_ns += now.saturating_sub(self.slice_start_ns[cpu]);
 		}
+		self.slice_start_ns[cpu] = now;
+		self.switch_fpu_state(caller_pid);
 	}
 	
-	fn get_process_with_name(&mut self, name: Name, periodic_only: bool) -> Option<&mut Process> {
-		self.processes_list.iter_mut()
-			.find(|c| c.as_ref()
-				.map(|c| (!periodic_only || c.get_process_scheduling_level() == SchedulingLevel::Periodic)
-					&& c.get_name() == name).unwrap_or(false))
-			.map(|c| c.as_mut().unwrap() ) // This is just to unwrap the process option, which we already checked
-	}
-	
-	fn switch_to_sporadic(&mut self) -> bool {
-		if let Some(new_process) = self.scheduler.sporadic_queue.front()
-			.map(|&c| c) // Copy the value early to workaround borrow checker limitation
-			.map(|new_pid| self.get_process_mut_with_pid(new_pid)
-				.expect("No entry with pid in process list, PID from sporadic queue, possibly caused by duplicate entries")) {
-			new_process.set_process_status(ProcessStatus::Running);
-			let new_process_pid = new_process.get_pid();
-			if self.currently_executing_process != new_process_pid {
-				let current_process = self.get_current_process_mut();
-				if current_process.get_process_status() == ProcessStatus::Running {
-					current_process.set_process_status(ProcessStatus::Scheduled);
-				}
-				self.currently_executing_process = new_process_pid;
-			}
-			true
-		} else {
-			false // No entries in sporadic queue
+	/// If we are about to return to a different process than `old_pid`, swap out the FPU/SSE registers.
+	/// Nothing is saved if `old_pid` has ended in the meantime.
+	fn switch_fpu_state(&mut self, old_pid: Pid) {
+		if self.table.get_current_pid() == old_pid {
+			return;
 		}
+		if let Some(old_process) = self.table.get_process_mut_with_pid(old_pid) {
+			old_process.get_fpu_state_mut().save();
+		}
+		self.table.get_current_process().get_fpu_state().restore();
 	}
 	
-	fn get_process_mut_with_pid(&mut self, pid: Pid) -> Option<&mut Process> {
-		if pid == 0 {
-			Some(&mut self.idle_process)
-		} else {
-			self.processes_list.get_mut(pid as usize - 1).and_then(|c| c.as_mut())
+	/// Override the quantum of a sporadic process, `None` uses the quantum of its current level.
+	/// Returns Err if there is no sporadic process with the pid, or the quantum is 0.
+	pub fn set_sporadic_quantum(&mut self, pid: Pid, quantum: Option<usize>) -> Result<(), ()> {
+		if quantum == Some(0) {
+			return Err(());
+		}
+		let process = self.table.get_process_mut_with_pid(pid).ok_or(())?;
+		if process.get_process_scheduling_level() != SchedulingLevel::Sporadic {
+			return Err(());
 		}
+		process.set_quantum(quantum);
+		Ok(())
 	}
 	
-	fn get_process_with_pid(&self, pid: Pid) -> Option<&Process> {
-		self.processes_list.get(pid as usize - 1).and_then(|c| c.as_ref())
+	pub fn get_process_info(&self, pid: Pid) -> Option<ProcessInfo> {
+		self.table.get_process_with_pid(pid).map(|c| c.info())
 	}
 	
-	fn get_current_process_mut(&mut self) -> &mut Process {
-		if self.currently_executing_process == 0 {
-			&mut self.idle_process
-		} else {
-			self.get_process_mut_with_pid(self.currently_executing_process)
-				.expect("Current process is None, someone forgot to change process number when terminating")
-		}
+	/// Info for every process, including the idle process
+	pub fn list_processes(&self) -> Vec<ProcessInfo> {
+		self.table.iter()
+			.map(|c| c.info())
+			.collect()
 	}
 	
-	fn get_current_process(&self) -> &Process {
-		if self.currently_executing_process == 0 {
-			&self.idle_process
-		} else {
-			self.get_process_with_pid(self.currently_executing_process)
-				.expect(&format!("Current process is None, someone forgot to change process number when terminating {}",
-								 self.currently_executing_process))
-		}
+	pub fn get_time(&self) -> usize {
+		self.time
 	}
 	
 	pub fn get_current_process_pid(&self) -> Pid {
-		self.currently_executing_process
+		self.table.get_current_pid()
 	}
 	
-	pub fn get_current_process_name(&self) -> Name {
-		self.get_current_process().get_name()
+	/// Pid of the process the current thread belongs to, or its own pid for a process
+	pub fn get_current_process_owner(&self) -> Pid {
+		self.table.get_current_process().get_owner_pid()
 	}
 	
-	pub fn get_current_scheduling_level(&self) -> SchedulingLevel {
-		self.get_current_process().get_process_scheduling_level()
+	pub fn get_current_process_name(&self) -> Name {
+		self.table.get_current_process().get_name()
 	}
 	
-	pub fn get_scheduled_name(&self) -> Name {
-		self.scheduler.get_current_periodic_entry().0
+	pub fn get_current_scheduling_level(&self) -> SchedulingLevel {
+		self.table.get_current_process().get_process_scheduling_level()
 	}
 	
-	pub fn unyield_current_periodic(&mut self) {
-		assert_eq!(self.scheduler.periodic_yielded, true,
-				   "I'm not actually sure this assert should be true, so just remove it if it's giving trouble");
-		self.scheduler.periodic_yielded = false;
-	}
 }
-use alloc::format;
 
-pub extern "C" fn idle_process() -> ! {
+extern "C" {
+	fn asm_enter_process(stack_pointer: usize) -> !;
+}
+
+/// Leave the boot stack behind and start running the idle process, which enables interrupts
+pub fn enter_idle_process() -> ! {
+	// Don't keep the lock, we are never coming back
+	let stack_p = PROCESS_MANAGER.lock().start_idle_process();
+	unsafe { asm_enter_process(stack_p.as_u64() as usize) }
+}
+
+/// Body of the idle process, zeroes free frames while there is nothing better to do
+pub extern "C" fn idle_process() {
 	loop {
-		x86_64::instructions::hlt(); // Save some (a lot of) power
+		// Interrupts off so we never get preempted while holding the frame allocator lock,
+		// zeroing one frame is quick enough
+		let zeroed_frame = x86_64::instructions::interrupts::without_interrupts(|| {
+			crate::FRAME_ALLOCATOR.try_lock()
+				.map(|mut frame_allocator| frame_allocator.zero_free_frame())
+				.unwrap_or(false)
+		});
+		if !zeroed_frame {
+			x86_64::instructions::hlt(); // Save some (a lot of) power
+		}
 	}
 }
\ No newline at end of file
@@ -0,0 +1,56 @@
+use crate::processes::{Pid, Name, SchedulingLevel, SporadicPriority, CreateProcessError, DeadlineParams, DeviceBudget};
+use crate::processes::process::Process;
+use crate::processes::table::ProcessTable;
+
+/// What the process manager has to do after the policy has handled a timer tick
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TickOutcome {
+	/// The policy has already picked the process to run
+	Continue,
+	/// The current process has to be terminated (e.g. it went over a budget it isn't allowed to go over)
+	TerminateCurrent,
+}
+
+/// Decides which process runs next.
+///
+/// The policy owns the run queues, while the process manager owns the processes themselves (in the `ProcessTable`),
+/// the sleep queue and the pids. Whenever a process changes state the manager tells the policy,
+/// and the policy then switches the table to whatever should run next.
+///
+/// Processes that are sleeping or blocked are taken off the run queues with `suspend_current`,
+/// and are not given back to the policy until `resume`.
+pub trait SchedulingPolicy {
+	/// Check if a process can be created, before anything has been allocated for it
+	fn admit(&self, table: &ProcessTable, level: SchedulingLevel, name: Name, priority: SporadicPriority,
+			 deadline: Option<DeadlineParams>) -> Result<(), CreateProcessError>;
+	
+	/// Check if a device can be created with `budget`, `admit` checks devices with the default budget
+	fn admit_device(&self, table: &ProcessTable, name: Name, budget: DeviceBudget) -> Result<(), CreateProcessError>;
+	
+	/// Check if a thread can be added to `owner`, it gets the same level, name, priority and deadline as the owner
+	fn admit_thread(&self, table: &ProcessTable, owner: &Process) -> Result<(), CreateProcessError>;
+	
+	/// A new process (or thread) has been put into the table, ready to be scheduled
+	fn process_created(&mut self, table: &mut ProcessTable, pid: Pid);
+	
+	/// A process has been taken out of the table, remove it from every run queue.
+	/// The table still has it as the current process if it was running.
+	fn process_ended(&mut self, table: &mut ProcessTable, process: &Process);
+	
+	/// The current process gave up the rest of its turn, switch to something else
+	fn yield_current(&mut self, table: &mut ProcessTable);
+	
+	/// The current process has just been marked sleeping or blocked, take it off the run queues.
+	/// `schedule` is called straight after.
+	fn suspend_current(&mut self, table: &mut ProcessTable);
+	
+	/// A suspended process is runnable again (its status is already back to scheduled)
+	fn resume(&mut self, table: &mut ProcessTable, pid: Pid);
+	
+	/// A timer tick has passed. `time` is the number of ticks since the scheduler started.
+	/// Unless the current process has to be terminated, the policy should have switched to the next process.
+	fn tick(&mut self, table: &mut ProcessTable, time: usize) -> TickOutcome;
+	
+	/// Switch to whatever should be running right now, `tick` is true if a timer tick has passed since the last call
+	fn schedule(&mut self, table: &mut ProcessTable, tick: bool);
+}
\ No newline at end of file
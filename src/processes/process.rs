@@ -1,8 +1,12 @@
 use x86_64::VirtAddr;
+use num_enum::TryFromPrimitive;
 use super::Name;
 use crate::kernel::os_terminate;
 use crate::memory::{alloc_stack, StackBounds};
-use crate::processes::{Pid, SchedulingLevel};
+use crate::processes::{Pid, SchedulingLevel, SporadicPriority, DEFAULT_SPORADIC_PRIORITY};
+use crate::processes::sporadic_queue::LEVEL_QUANTUM;
+use crate::processes::deadline::DeadlineServer;
+use crate::processes::fpu::FpuState;
 use crate::println;
 
 global_asm!(include_str!("../setup_process_stack.s"));
@@ -11,13 +15,97 @@ extern "C" {
 	fn asm_fake_register(new_stack_addr: usize, terminate_func_addr: usize, program_start_addr: usize) -> usize;
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u64)]
 pub enum ProcessStatus {
 	Yielded = 0,
 	Running = 1,
 	Scheduled = 2,
+	/// Waiting in the sleep queue, must not be scheduled until woken up
+	Sleeping = 3,
+	/// Waiting in a semaphore queue, must not be scheduled until signaled
+	Blocked = 4,
+}
+
+impl ProcessStatus {
+	/// Suspended processes are off every run queue until someone resumes them
+	pub fn is_suspended(&self) -> bool {
+		*self == ProcessStatus::Sleeping || *self == ProcessStatus::Blocked
+	}
+}
+
+/// What to do with a device process that is still running when it runs out of budget
+#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u64)]
+pub enum DeviceOverrunPolicy {
+	/// Just count and report the overrun, and let it keep running
+	Log,
+	/// Take it off the device queue, it picks up where it left off the next time its period comes around
+	Preempt,
+	/// Permanently move it down to the sporadic queue
+	Demote,
+	/// Terminate it
+	Kill,
 }
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct DeviceBudget {
+	/// Number of ticks the device can run for each time it is released
+	pub ticks: usize,
+	pub policy: DeviceOverrunPolicy,
+}
+
+impl DeviceBudget {
+	/// Devices are supposed to finish within the tick they are released in
+	pub const DEFAULT: DeviceBudget = DeviceBudget {
+		ticks: 1,
+		policy: DeviceOverrunPolicy::Preempt,
+	};
+}
+
+/// CPU time used by a process, updated on every context switch and syscall
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct CpuUsage {
+	/// Timer ticks that fired while this process was running
+	pub ticks: usize,
+	/// Time spent running the process itself, measured with the TSC
+	pub user_ns: u64,
+	/// Time spent in the kernel handling syscalls made by this process
+	pub kernel_ns: u64,
+	/// Times it was switched out while it still wanted to run
+	pub preemptions: usize,
+	/// Times it called `os_yield()`
+	pub yields: usize,
+}
+
+impl CpuUsage {
+	pub const ZERO: CpuUsage = CpuUsage {
+		ticks: 0,
+		user_ns: 0,
+		kernel_ns: 0,
+		preemptions: 0,
+		yields: 0,
+	};
+}
+
+/// Snapshot of a process for looking at from outside the process manager
+#[derive(Clone, Debug)]
+pub struct ProcessInfo {
+	pub pid: Pid,
+	pub name: Name,
+	pub level: SchedulingLevel,
+	pub status: ProcessStatus,
+	pub arg: i32,
+	/// Current level in the sporadic queue, only meaningful for sporadic processes
+	pub sporadic_priority: SporadicPriority,
+	/// Number of times this process went over its device budget
+	pub device_overruns: usize,
+	/// Number of jobs of a deadline process that didn't finish by their deadline
+	pub deadline_misses: usize,
+	pub usage: CpuUsage,
+	/// Pid of the process this thread belongs to, None for processes themselves
+	pub owner: Option<Pid>,
+}
 
 #[derive(Clone, Debug)]
 pub struct Process {
@@ -30,13 +118,31 @@ pub struct Process {
 	stack_bounds: StackBounds,
 	stack_pointer: VirtAddr,
 	arg: i32,
+	device_budget: DeviceBudget,
+	/// Ticks used since the device was last released
+	device_ticks_used: usize,
+	device_overruns: usize,
+	sporadic_priority: SporadicPriority,
+	/// Ticks run since the last change of sporadic priority
+	sporadic_ticks_used: usize,
+	/// Scheduler time this process was last running at, used to age waiting sporadic processes
+	last_ran: usize,
+	/// Overrides the quantum of the sporadic level
+	quantum: Option<usize>,
+	/// Ticks run since this process last got to the back of its level
+	quantum_used: usize,
+	/// Only used by deadline processes
+	deadline_server: Option<DeadlineServer>,
+	usage: CpuUsage,
+	/// Saved whenever the process is switched out, and restored when it's switched back in
+	fpu_state: FpuState,
+	/// Set for threads, the process that spawned them (threads spawned by threads belong to the same process)
+	owner: Option<Pid>,
 }
 
 impl Process {
 	// TODO: Implement error type
 	pub fn new(pid: Pid, level: SchedulingLevel, name: Name, arg: i32, program_start: extern "C" fn()) -> Process {
-		assert_ne!(level, SchedulingLevel::Idle, "Please use idle() to create idle process");
-		
 		let stack_bounds = alloc_stack(32,
 									   &mut *crate::TEMP_MAPPER.lock().as_mut().unwrap(),
 									   &mut *crate::FRAME_ALLOCATOR.lock()).unwrap();
@@ -61,18 +167,18 @@ impl Process {
 			stack_pointer: VirtAddr::new(fake_int_sp as u64),
 			name,
 			arg,
-		}
-	}
-	
-	pub const fn idle() -> Process {
-		Process {
-			pid: 0,
-			level: SchedulingLevel::Idle,
-			status: ProcessStatus::Running,
-			stack_bounds: StackBounds::zero(),
-			stack_pointer: VirtAddr::zero(),
-			name: 0,
-			arg: 0,
+			device_budget: DeviceBudget::DEFAULT,
+			device_ticks_used: 0,
+			device_overruns: 0,
+			sporadic_priority: DEFAULT_SPORADIC_PRIORITY,
+			sporadic_ticks_used: 0,
+			last_ran: 0,
+			quantum: None,
+			quantum_used: 0,
+			deadline_server: None,
+			usage: CpuUsage::ZERO,
+			fpu_state: FpuState::new(),
+			owner: None,
 		}
 	}
 	
@@ -115,4 +221,132 @@ impl Process {
 	pub fn get_process_status(&self) -> ProcessStatus {
 		self.status
 	}
+	
+	pub fn set_process_scheduling_level(&mut self, new_level: SchedulingLevel) {
+		self.level = new_level;
+	}
+	
+	pub fn get_device_budget(&self) -> DeviceBudget {
+		self.device_budget
+	}
+	
+	pub fn set_device_budget(&mut self, new_budget: DeviceBudget) {
+		self.device_budget = new_budget;
+	}
+	
+	/// Count one more tick against the device budget, returns true if the budget has now been used up
+	pub fn charge_device_tick(&mut self) -> bool {
+		self.device_ticks_used += 1;
+		if self.device_ticks_used >= self.device_budget.ticks {
+			self.device_overruns += 1;
+			true
+		} else {
+			false
+		}
+	}
+	
+	/// Called whenever the device leaves the device queue, so the next release gets a full budget
+	pub fn reset_device_ticks(&mut self) {
+		self.device_ticks_used = 0;
+	}
+	
+	pub fn get_sporadic_priority(&self) -> SporadicPriority {
+		self.sporadic_priority
+	}
+	
+	/// Also resets the ticks used at the current priority
+	pub fn set_sporadic_priority(&mut self, new_priority: SporadicPriority) {
+		self.sporadic_priority = new_priority;
+		self.sporadic_ticks_used = 0;
+	}
+	
+	/// Count one more tick run at the current sporadic priority, returns the total
+	pub fn charge_sporadic_tick(&mut self) -> usize {
+		self.sporadic_ticks_used += 1;
+		self.sporadic_ticks_used
+	}
+	
+	/// Number of ticks this process can run before rotating to the back of its sporadic level
+	pub fn get_quantum(&self) -> usize {
+		self.quantum.unwrap_or(LEVEL_QUANTUM[self.sporadic_priority])
+	}
+	
+	/// `None` goes back to using the quantum of the current level
+	pub fn set_quantum(&mut self, new_quantum: Option<usize>) {
+		self.quantum = new_quantum;
+	}
+	
+	/// Count one more tick against the quantum, returns true if the quantum has now run out
+	pub fn charge_quantum_tick(&mut self) -> bool {
+		self.quantum_used += 1;
+		self.quantum_used >= self.get_quantum()
+	}
+	
+	pub fn reset_quantum(&mut self) {
+		self.quantum_used = 0;
+	}
+	
+	pub fn get_deadline_server(&self) -> Option<&DeadlineServer> {
+		self.deadline_server.as_ref()
+	}
+	
+	pub fn get_deadline_server_mut(&mut self) -> Option<&mut DeadlineServer> {
+		self.deadline_server.as_mut()
+	}
+	
+	pub fn set_deadline_server(&mut self, server: DeadlineServer) {
+		self.deadline_server = Some(server);
+	}
+	
+	pub fn get_usage(&self) -> CpuUsage {
+		self.usage
+	}
+	
+	pub fn get_usage_mut(&mut self) -> &mut CpuUsage {
+		&mut self.usage
+	}
+	
+	pub fn get_fpu_state(&self) -> &FpuState {
+		&self.fpu_state
+	}
+	
+	pub fn get_fpu_state_mut(&mut self) -> &mut FpuState {
+		&mut self.fpu_state
+	}
+	
+	pub fn get_last_ran(&self) -> usize {
+		self.last_ran
+	}
+	
+	pub fn set_last_ran(&mut self, time: usize) {
+		self.last_ran = time;
+	}
+	
+	pub fn get_owner(&self) -> Option<Pid> {
+		self.owner
+	}
+	
+	pub fn set_owner(&mut self, owner: Pid) {
+		self.owner = Some(owner);
+	}
+	
+	/// Pid of the process this belongs to, which is its own pid if it isn't a thread
+	pub fn get_owner_pid(&self) -> Pid {
+		self.owner.unwrap_or(self.pid)
+	}
+	
+	pub fn info(&self) -> ProcessInfo {
+		ProcessInfo {
+			pid: self.pid,
+			name: self.name,
+			level: self.level,
+			status: self.status,
+			arg: self.arg,
+			sporadic_priority: self.sporadic_priority,
+			device_overruns: self.device_overruns,
+			deadline_misses: self.deadline_server.map(|c| c.get_misses()).unwrap_or(0),
+			usage: self.usage,
+			owner: self.owner,
+		}
+	}
 }
\ No newline at end of file
@@ -1,19 +1,32 @@
 use alloc::vec::Vec;
 use alloc::collections::VecDeque;
 use crate::processes::{Pid, Name};
+use crate::processes::sporadic_queue::SporadicQueue;
 #[allow(unused_imports)]
 use crate::println;
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PeriodicOrderError {
+	/// The order has no entries, so there is nothing to cycle through
+	Empty,
+	/// A slot was given a length of 0 ticks
+	ZeroLengthSlot(Name),
+	/// A running periodic process has a name that doesn't appear in the order, so it will never run
+	UnusedName(Name),
+}
+
 #[derive(Debug, Default)]
 pub struct Scheduler {
 	pub time: usize,
 	/// (Name, Time)
 	pub periodic_order: Vec<(Name, usize)>,
+	/// Replacement order, swapped in at the next frame boundary
+	pub pending_periodic_order: Option<Vec<(Name, usize)>>,
 	pub periodic_time: usize,
 	pub periodic_index: usize,
 	pub periodic_yielded: bool,
 	pub device_queue: VecDeque<Pid>,
-	pub sporadic_queue: VecDeque<Pid>,
+	pub sporadic_queue: SporadicQueue,
 }
 
 impl Scheduler {
@@ -25,6 +38,35 @@ impl Scheduler {
 		}
 	}
 	
+	/// Check that the order is usable on its own, without looking at what processes exist
+	pub fn validate_periodic_order(periodic_order: &[(Name, usize)]) -> Result<(), PeriodicOrderError> {
+		if periodic_order.is_empty() {
+			return Err(PeriodicOrderError::Empty);
+		}
+		if let Some(&(name, _)) = periodic_order.iter().find(|&&(_, time)| time == 0) {
+			return Err(PeriodicOrderError::ZeroLengthSlot(name));
+		}
+		Ok(())
+	}
+	
+	/// Queue a new order to be used from the start of the next frame.
+	/// If the scheduler hasn't started ticking yet, the order is used immediately instead.
+	pub fn set_periodic_order(&mut self, periodic_order: Vec<(Name, usize)>) {
+		if self.time == 0 {
+			self.periodic_index = 0;
+			self.periodic_time = periodic_order[0].1;
+			self.periodic_order = periodic_order;
+			self.pending_periodic_order = None;
+		} else {
+			self.pending_periodic_order = Some(periodic_order);
+		}
+	}
+	
+	/// The order that will be in use once any pending change goes through
+	pub fn effective_periodic_order(&self) -> &[(Name, usize)] {
+		self.pending_periodic_order.as_ref().unwrap_or(&self.periodic_order)
+	}
+	
 	/// Check if periodic_time is 0, if yes change to next periodic period, returning new process Name,
 	/// otherwise, return `None`
 	pub fn check_and_change_periodic(&mut self) -> (Name, bool) {
@@ -41,10 +83,42 @@ impl Scheduler {
 	
 	fn increment_periodic_index(&mut self) {
 		self.periodic_index = (self.periodic_index + 1) % self.periodic_order.len();
+		// Only swap tables at a frame boundary, so a frame is never cut halfway
+		if self.periodic_index == 0 {
+			if let Some(new_order) = self.pending_periodic_order.take() {
+				self.periodic_order = new_order;
+			}
+		}
 		// println!("Incremented Index: {}", self.periodic_index);
 	}
 	
 	pub fn get_current_periodic_entry(&self) -> (Name, usize) {
 		self.periodic_order[self.periodic_index]
 	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Scheduler, PeriodicOrderError};
+	use alloc::vec;
+	use crate::{serial_print, serial_println};
+	
+	#[test_case]
+	fn test_periodic_order_frame_boundary() {
+		serial_print!("test_periodic_order_frame_boundary... ");
+		
+		assert_eq!(Scheduler::validate_periodic_order(&[]), Err(PeriodicOrderError::Empty));
+		assert_eq!(Scheduler::validate_periodic_order(&[(1, 2), (2, 0)]), Err(PeriodicOrderError::ZeroLengthSlot(2)));
+		
+		let mut scheduler = Scheduler::new(vec![(1, 1), (2, 1)]);
+		scheduler.time = 1; // Pretend we have started, so the change is deferred
+		scheduler.set_periodic_order(vec![(3, 1)]);
+		scheduler.periodic_time = 0;
+		assert_eq!(scheduler.check_and_change_periodic(), (2, true));
+		scheduler.periodic_time = 0;
+		assert_eq!(scheduler.check_and_change_periodic(), (3, true));
+		assert!(scheduler.pending_periodic_order.is_none());
+		
+		serial_println!("[ok]");
+	}
 }
\ No newline at end of file
@@ -0,0 +1,99 @@
+use alloc::collections::VecDeque;
+use crate::processes::Pid;
+
+/// 0 is the highest priority
+pub type SporadicPriority = usize;
+
+pub const SPORADIC_PRIORITY_LEVELS: usize = 4;
+pub const LOWEST_SPORADIC_PRIORITY: SporadicPriority = SPORADIC_PRIORITY_LEVELS - 1;
+pub const DEFAULT_SPORADIC_PRIORITY: SporadicPriority = 1;
+
+/// Ticks a process can run for at each level before being demoted to the next one,
+/// the lowest level never gets demoted so its entry doesn't matter
+pub const LEVEL_ALLOTMENT: [usize; SPORADIC_PRIORITY_LEVELS] = [2, 4, 8, 0];
+/// Default number of ticks a process runs for before the next process at the same level gets a turn,
+/// lower levels get longer slices since they are mostly CPU bound anyway
+pub const LEVEL_QUANTUM: [usize; SPORADIC_PRIORITY_LEVELS] = [1, 2, 4, 8];
+/// Ticks a process can wait in a queue without running before it gets promoted one level
+pub const AGING_TICKS: usize = 100;
+
+/// Multi level feedback queue for sporadic processes.
+///
+/// Round robin within a level, and the highest non empty level always goes first.
+#[derive(Debug, Default)]
+pub struct SporadicQueue {
+	levels: [VecDeque<Pid>; SPORADIC_PRIORITY_LEVELS],
+}
+
+impl SporadicQueue {
+	pub fn push_back(&mut self, pid: Pid, priority: SporadicPriority) {
+		self.levels[priority].push_back(pid);
+	}
+	
+	/// Every queued pid, in the order they would run, so the first is the next to run
+	pub fn iter(&self) -> impl Iterator<Item=Pid> + '_ {
+		self.levels.iter().flat_map(|level| level.iter().copied())
+	}
+	
+	/// Returns true if the pid was in the queue
+	pub fn remove(&mut self, pid: Pid) -> bool {
+		for level in self.levels.iter_mut() {
+			if let Some(idx) = level.iter().position(|&c| c == pid) {
+				level.remove(idx);
+				return true;
+			}
+		}
+		false
+	}
+	
+	/// Move the pid to the back of its level, letting the other processes at that level go first
+	pub fn requeue(&mut self, pid: Pid) {
+		if let Some(priority) = self.priority_of(pid) {
+			self.remove(pid);
+			self.push_back(pid, priority);
+		}
+	}
+	
+	/// Move the pid to the back of another level
+	pub fn change_priority(&mut self, pid: Pid, new_priority: SporadicPriority) {
+		if self.remove(pid) {
+			self.push_back(pid, new_priority);
+		}
+	}
+	
+	pub fn priority_of(&self, pid: Pid) -> Option<SporadicPriority> {
+		self.levels.iter().position(|level| level.contains(&pid))
+	}
+	
+	/// Every queued pid below the top level, highest level first
+	pub fn agable_pids(&self) -> impl Iterator<Item=Pid> + '_ {
+		self.levels.iter().skip(1).flat_map(|level| level.iter().copied())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::SporadicQueue;
+	use crate::{serial_print, serial_println};
+	
+	#[test_case]
+	fn test_sporadic_queue_levels() {
+		serial_print!("test_sporadic_queue_levels... ");
+		
+		let mut queue = SporadicQueue::default();
+		assert_eq!(queue.iter().next(), None);
+		queue.push_back(1, 2);
+		queue.push_back(2, 1);
+		queue.push_back(3, 1);
+		assert_eq!(queue.iter().next(), Some(2));
+		queue.requeue(2);
+		assert_eq!(queue.iter().next(), Some(3));
+		queue.change_priority(1, 0);
+		assert_eq!(queue.iter().next(), Some(1));
+		assert!(queue.remove(1));
+		assert!(!queue.remove(1));
+		assert_eq!(queue.priority_of(2), Some(1));
+		
+		serial_println!("[ok]");
+	}
+}
\ No newline at end of file
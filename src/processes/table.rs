@@ -0,0 +1,129 @@
+use alloc::vec::Vec;
+use alloc::vec;
+use alloc::format;
+use crate::processes::{Process, Pid};
+use crate::processes::process::ProcessStatus;
+use crate::smp::{cpu_index, MAX_CPUS};
+
+/// Every process that exists, plus which one is currently executing on each CPU.
+///
+/// This is what scheduling policies get to look at and change, the run queues themselves live in the policy.
+/// The current process, idle process and switching all refer to the CPU the caller is running on.
+pub struct ProcessTable {
+	processes_list: Vec<Option<Process>>,
+	/// Per CPU, 0 until the idle process of the CPU has been created
+	idle_pids: [Pid; MAX_CPUS],
+	/// Per CPU, 0 until the idle process of the CPU starts running
+	currently_executing: [Pid; MAX_CPUS],
+}
+
+impl ProcessTable {
+	pub fn new() -> Self {
+		Self {
+			processes_list: vec![None; 2],
+			idle_pids: [0; MAX_CPUS],
+			currently_executing: [0; MAX_CPUS],
+		}
+	}
+	
+	pub fn insert(&mut self, process: Process) {
+		if process.get_idx() >= self.processes_list.len() {
+			self.processes_list.resize(process.get_idx() + 1, None);
+		}
+		assert!(self.processes_list[process.get_idx()].is_none(), "PID of new process is not empty");
+		let idx = process.get_idx();
+		self.processes_list[idx] = Some(process);
+	}
+	
+	pub fn take(&mut self, pid: Pid) -> Option<Process> {
+		self.processes_list.get_mut(pid as usize - 1).and_then(|c| c.take())
+	}
+	
+	/// Every process, including idle
+	pub fn iter(&self) -> impl Iterator<Item=&Process> {
+		self.processes_list.iter().filter_map(|c| c.as_ref())
+	}
+	
+	pub fn iter_mut(&mut self) -> impl Iterator<Item=&mut Process> {
+		self.processes_list.iter_mut().filter_map(|c| c.as_mut())
+	}
+	
+	pub fn get_process_mut_with_pid(&mut self, pid: Pid) -> Option<&mut Process> {
+		(pid as usize).checked_sub(1) // Pids start at 1
+			.and_then(move |idx| self.processes_list.get_mut(idx))
+			.and_then(|c| c.as_mut())
+	}
+	
+	pub fn get_process_with_pid(&self, pid: Pid) -> Option<&Process> {
+		(pid as usize).checked_sub(1) // Pids start at 1
+			.and_then(|idx| self.processes_list.get(idx))
+			.and_then(|c| c.as_ref())
+	}
+	
+	/// Index of the CPU the caller is running on, which everything about the current process refers to
+	pub fn get_cpu(&self) -> usize {
+		cpu_index()
+	}
+	
+	pub fn get_current_process_mut(&mut self) -> &mut Process {
+		let current_pid = self.get_current_pid();
+		self.get_process_mut_with_pid(current_pid)
+			.expect("Current process is None, someone forgot to change process number when terminating")
+	}
+	
+	pub fn get_current_process(&self) -> &Process {
+		let current_pid = self.get_current_pid();
+		self.get_process_with_pid(current_pid)
+			.expect(&format!("Current process is None, someone forgot to change process number when terminating {}",
+							 current_pid))
+	}
+	
+	pub fn get_current_pid(&self) -> Pid {
+		self.currently_executing[self.get_cpu()]
+	}
+	
+	/// Running, but not on this CPU, so it can't be switched to (or have its stack touched) from here
+	pub fn is_running_elsewhere(&self, pid: Pid) -> bool {
+		pid != self.get_current_pid() && self.get_process_with_pid(pid)
+			.map(|c| c.get_process_status() == ProcessStatus::Running)
+			.unwrap_or(false)
+	}
+	
+	/// Make `new_pid` the running process. The previous process goes back to scheduled if it was still running,
+	/// processes that yielded or got suspended keep their status.
+	pub fn switch_to(&mut self, new_pid: Pid) {
+		assert!(!self.is_running_elsewhere(new_pid), "Process {} is already running on another CPU", new_pid);
+		let cpu = self.get_cpu();
+		if self.currently_executing[cpu] != new_pid {
+			let current_process = self.get_current_process_mut();
+			if current_process.get_process_status() == ProcessStatus::Running {
+				current_process.set_process_status(ProcessStatus::Scheduled);
+			}
+			self.currently_executing[cpu] = new_pid;
+		}
+		self.get_current_process_mut().set_process_status(ProcessStatus::Running);
+	}
+	
+	pub fn get_idle_pid(&self) -> Pid {
+		self.idle_pids[self.get_cpu()]
+	}
+	
+	/// Make an already inserted process the idle process of this CPU, and the one that is running
+	pub fn set_idle(&mut self, pid: Pid) {
+		let cpu = self.get_cpu();
+		assert_eq!(self.idle_pids[cpu], 0, "There is already an idle process on CPU {}", cpu);
+		self.idle_pids[cpu] = pid;
+		self.switch_to_idle_unchecked();
+	}
+	
+	pub fn switch_to_idle(&mut self) {
+		self.switch_to(self.get_idle_pid());
+	}
+	
+	/// Switch to idle without touching the status of the previous process, used when it no longer exists
+	pub fn switch_to_idle_unchecked(&mut self) {
+		let cpu = self.get_cpu();
+		self.currently_executing[cpu] = self.idle_pids[cpu];
+		self.get_current_process_mut().set_process_status(ProcessStatus::Running);
+	}
+}
\ No newline at end of file
@@ -1,6 +1,7 @@
 .intel_syntax noprefix
 
 .global asm_fake_register
+.global asm_enter_process
 
 //; asm_fake_register(new_stack_pointer: usize, terminate_func_addr: usize, program_start_addr: usize) -> usize
 asm_fake_register:
@@ -41,4 +42,26 @@ asm_fake_register:
 	mov rax, rsp // Save new stack pointer in rax, the C return register
 	mov rsp, r8 // Get back our original stack pointer
 
-    ret
\ No newline at end of file
+    ret
+
+//; asm_enter_process(stack_pointer: usize) -> !
+//; Start running a process from a stack saved by an interrupt (or faked by asm_fake_register), without saving anything
+asm_enter_process:
+	mov rsp, rdi
+	// Same order as interrupt_pop!
+	pop r15
+	pop r14
+	pop r13
+	pop r12
+	pop rbp
+	pop rbx
+	pop r11
+	pop r10
+	pop r9
+	pop r8
+	pop rsi
+	pop rdi
+	pop rdx
+	pop rcx
+	pop rax
+	iretq
\ No newline at end of file
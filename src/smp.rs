@@ -0,0 +1,180 @@
+use core::sync::atomic::{AtomicUsize, AtomicBool, Ordering};
+use x86_64::VirtAddr;
+use x86_64::registers::control::Cr3;
+use crate::interrupts::lapic;
+use crate::interrupts::hardware::{self, InterruptIndex};
+use crate::{println, eprintln};
+
+global_asm!(include_str!("ap_trampoline.s"));
+
+extern "C" {
+	static ap_trampoline_start: u8;
+	static ap_trampoline_end: u8;
+	static ap_long_mode: u8;
+	static ap_gdt: u8;
+	static ap_gdt_pointer: u8;
+	static ap_far_pointer: u8;
+	static ap_trampoline_args: u8;
+}
+
+/// Most CPUs the kernel will use, the per CPU state in the process manager is sized for this many
+pub const MAX_CPUS: usize = 8;
+/// Index of the CPU the bootloader started us on
+pub const BOOT_CPU: usize = 0;
+
+/// Same layout as `ap_trampoline_args` in ap_trampoline.s
+#[repr(C)]
+struct TrampolineArgs {
+	cr3: u64,
+	stack_top: u64,
+	entry: u64,
+	cpu_index: u64,
+}
+
+/// Kernel stack of each AP, it starts on it and then uses it for interrupts and syscalls
+const AP_STACK_PAGES: u64 = 32;
+/// How long to wait for an AP before giving up on it
+const AP_STARTUP_TIMEOUT_NS: u64 = 100_000_000;
+
+/// Indexed by APIC ID, APIC IDs don't have to be contiguous but the CPU indices are
+static CPU_INDEX_BY_APIC_ID: [AtomicUsize; 256] = [AtomicUsize::new(BOOT_CPU); 256];
+static CPU_COUNT: AtomicUsize = AtomicUsize::new(1);
+/// Set by the AP that is starting, once it's done with the trampoline
+static AP_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Index (from 0 to `cpu_count()`) of the CPU this runs on
+pub fn cpu_index() -> usize {
+	if !lapic::is_mapped() {
+		return BOOT_CPU; // Nothing else has been started yet
+	}
+	CPU_INDEX_BY_APIC_ID[lapic::id() as usize].load(Ordering::Relaxed)
+}
+
+pub fn cpu_count() -> usize {
+	CPU_COUNT.load(Ordering::Relaxed)
+}
+
+/// Start every application processor (AP) in the MADT, each of them ends up running the scheduler from its own idle process.
+///
+/// They are started one at a time with INIT-SIPI-SIPI (they all share the one trampoline page),
+/// and the ones that never check in are skipped. Needs `interrupt_init` to have set up the local APIC.
+pub fn smp_init() {
+	let apic_ids = match crate::acpi::madt() {
+		Some(madt) if hardware::apic_mode() => &madt.processor_apic_ids,
+		_ => {
+			println!("Running on 1 CPU");
+			return;
+		}
+	};
+	
+	let trampoline_frame = match crate::FRAME_ALLOCATOR.lock().take_low_frame() {
+		Some(frame) => frame,
+		None => {
+			eprintln!("WARNING: No memory below 1MiB for the AP trampoline, only using the boot CPU");
+			return;
+		}
+	};
+	crate::memory::identity_map(trampoline_frame,
+								&mut *crate::TEMP_MAPPER.lock().as_mut().unwrap(),
+								&mut *crate::FRAME_ALLOCATOR.lock())
+		.expect("Failed to identity map the AP trampoline");
+	let trampoline_addr = trampoline_frame.start_address().as_u64();
+	let args = unsafe { copy_trampoline(trampoline_addr) };
+	
+	let boot_apic_id = lapic::id();
+	for &apic_id in apic_ids.iter().filter(|&&apic_id| apic_id != boot_apic_id) {
+		let cpu = cpu_count();
+		if cpu == MAX_CPUS {
+			eprintln!("WARNING: Only using the first {} CPUs", MAX_CPUS);
+			break;
+		}
+		let stack = crate::memory::alloc_stack(AP_STACK_PAGES,
+											   &mut *crate::TEMP_MAPPER.lock().as_mut().unwrap(),
+											   &mut *crate::FRAME_ALLOCATOR.lock())
+			.expect("Failed to allocate AP stack");
+		unsafe {
+			*args = TrampolineArgs {
+				cr3: Cr3::read().0.start_address().as_u64(),
+				stack_top: stack.end().as_u64(),
+				entry: ap_main as u64,
+				cpu_index: cpu as u64,
+			};
+		}
+		CPU_INDEX_BY_APIC_ID[apic_id as usize].store(cpu, Ordering::Relaxed);
+		
+		if start_ap(apic_id, (trampoline_addr >> 12) as u8) {
+			CPU_COUNT.fetch_add(1, Ordering::Relaxed);
+		} else {
+			// It might just be slow, and still get to the trampoline (and its stack) later. INIT parks it until the
+			// next startup IPI, so it can't pick up the args of the next AP, and the stack is never freed in case
+			// it's already running on it.
+			lapic::send_init(apic_id);
+			CPU_INDEX_BY_APIC_ID[apic_id as usize].store(BOOT_CPU, Ordering::Relaxed);
+			eprintln!("WARNING: CPU with APIC id {} didn't start, leaving it parked", apic_id);
+		}
+	}
+	println!("Running on {} CPUs", cpu_count());
+}
+
+/// Copy the trampoline code to `trampoline_addr` (identity mapped), and fill in the addresses that depend on where it is.
+/// Returns where the arguments for the next AP go.
+unsafe fn copy_trampoline(trampoline_addr: u64) -> *mut TrampolineArgs {
+	let start = &ap_trampoline_start as *const u8;
+	let offset_of = |symbol: &u8| symbol as *const u8 as u64 - start as u64;
+	let len = offset_of(&ap_trampoline_end) as usize;
+	assert!(len <= 4096, "AP trampoline doesn't fit in a page");
+	
+	let dest = trampoline_addr as *mut u8;
+	core::ptr::copy_nonoverlapping(start, dest, len);
+	
+	let gdt_base = (trampoline_addr + offset_of(&ap_gdt)) as u32;
+	let long_mode_addr = (trampoline_addr + offset_of(&ap_long_mode)) as u32;
+	// Skip the 16 bit limit of the GDT pointer
+	core::ptr::write_unaligned(dest.add(offset_of(&ap_gdt_pointer) as usize + 2) as *mut u32, gdt_base);
+	core::ptr::write_unaligned(dest.add(offset_of(&ap_far_pointer) as usize) as *mut u32, long_mode_addr);
+	
+	dest.add(offset_of(&ap_trampoline_args) as usize) as *mut TrampolineArgs
+}
+
+/// INIT-SIPI-SIPI, returns true if the AP made it to `ap_main`
+fn start_ap(apic_id: u8, start_page: u8) -> bool {
+	AP_STARTED.store(false, Ordering::SeqCst);
+	lapic::send_init(apic_id);
+	wait_ns(10_000_000);
+	lapic::send_startup(apic_id, start_page);
+	wait_ns(200_000);
+	if !AP_STARTED.load(Ordering::SeqCst) {
+		lapic::send_startup(apic_id, start_page); // The second one is in case the first got lost
+	}
+	
+	let start = crate::time::time_ns();
+	while !AP_STARTED.load(Ordering::SeqCst) {
+		if crate::time::time_ns() - start > AP_STARTUP_TIMEOUT_NS {
+			return false;
+		}
+	}
+	true
+}
+
+fn wait_ns(ns: u64) {
+	let start = crate::time::time_ns();
+	while crate::time::time_ns() - start < ns {}
+}
+
+/// Where each AP ends up after the trampoline, on its own kernel stack with interrupts off.
+/// Sets up everything that is per CPU, then starts scheduling from its own idle process.
+extern "C" fn ap_main(cpu: usize, stack_top: u64) -> ! {
+	assert_eq!(cpu_index(), cpu, "AP started with the wrong index");
+	// The trampoline page and args are free for the next AP from here on
+	AP_STARTED.store(true, Ordering::SeqCst);
+	
+	lapic::enable();
+	let tss = crate::gdt::gdt_init_ap(VirtAddr::new(stack_top));
+	crate::interrupts::idt_init();
+	crate::processes::fpu::fpu_init();
+	crate::kernel::syscall_init(tss);
+	lapic::start_timer(InterruptIndex::ApicTimer as u8);
+	
+	crate::processes::PROCESS_MANAGER.lock().create_idle_process();
+	crate::processes::enter_idle_process(); // Enables interrupts
+}
\ No newline at end of file
@@ -8,9 +8,9 @@
 //
 // type Mutex = Semaphore;
 
-use alloc::collections::{BTreeSet, BTreeMap};
+use alloc::collections::{VecDeque, BTreeMap};
 use spin::{Mutex, RwLock};
-use crate::processes::Name;
+use crate::processes::Pid;
 use lazy_static::lazy_static;
 use core::sync::atomic::{AtomicI32, Ordering};
 
@@ -25,7 +25,8 @@ lazy_static! { // Using a spinlock semaphore to control my semaphore lol
 pub struct Semaphore {
 	initial_count: i32,
 	count: AtomicI32,
-	queue: Mutex<BTreeSet<Name>>,
+	/// Blocked processes, in the order they called wait
+	queue: Mutex<VecDeque<Pid>>,
 }
 
 impl Semaphore {
@@ -33,7 +34,7 @@ impl Semaphore {
 		Semaphore {
 			initial_count: count,
 			count: AtomicI32::new(count),
-			queue: Mutex::new(BTreeSet::new()),
+			queue: Mutex::new(VecDeque::new()),
 		}
 	}
 	
@@ -54,17 +55,28 @@ impl Semaphore {
 		}
 	}
 	
-	pub fn add_to_wait_queue(&self, name: Name) {
-		assert_eq!(self.queue.lock().insert(name), false, "Wait queue already has element");
+	pub fn add_to_wait_queue(&self, pid: Pid) {
+		let mut queue = self.queue.lock();
+		assert!(!queue.contains(&pid), "Wait queue already has element");
+		queue.push_back(pid);
 	}
 	
-	pub fn check_and_pop_if_exists(&self, name: Name) -> bool {
-		self.queue.lock().remove(&name)
+	pub fn remove_from_wait_queue(&self, pid: Pid) {
+		self.queue.lock().retain(|&c| c != pid);
 	}
 	
-	/// Returns true if current number of users is >= 0
-	pub fn signal(&self) -> bool {
+	/// Returns the pid of the waiting process that now holds the semaphore, if any.
+	///
+	/// The count is handed straight over to the first waiter, so it never has to try acquiring again.
+	pub fn signal(&self) -> Option<Pid> {
+		let mut queue = self.queue.lock();
 		let old = self.count.fetch_add(1, Ordering::Relaxed);
-		old + 1 >= 0
+		if old + 1 > 0 {
+			if let Some(pid) = queue.pop_front() {
+				self.count.fetch_sub(1, Ordering::Relaxed);
+				return Some(pid);
+			}
+		}
+		None
 	}
 }
\ No newline at end of file
@@ -1,17 +1,19 @@
 use super::applications::*;
 use crate::kernel::*;
-use crate::processes::SchedulingLevel;
+use crate::processes::{SchedulingLevel, DeviceBudget, DeviceOverrunPolicy, SPORADIC_PRIORITY_LEVELS, DeadlineParams};
+use alloc::vec::Vec;
+use core::sync::atomic::Ordering;
 use crate::sync::SemaphoreId;
 use crate::println;
 use crate::ipc::FifoKey;
-use crate::interrupts::{syscall1, SyscallCommand};
+use crate::interrupts::syscall::{syscall, SyscallCommand};
 
 pub const TEST_SEMAPHORE_ID: SemaphoreId = 1024;
 // const PRINT_FIFO_KEY: FifoKey = 2048;
 
 pub extern "C" fn run_tests() {
 	println!("Initiating tests");
-	let fifo_key = os_init_fifo();
+	let fifo_key = os_init_fifo(1024).unwrap();
 	// Test case complete signaler
 	os_init_sem(TEST_SEMAPHORE_ID, required_count(2));
 	println!("Signal test ...");
@@ -20,8 +22,58 @@ pub extern "C" fn run_tests() {
 	println!("Signal test complete");
 	println!("IPC test ...");
 	os_create(123, SchedulingLevel::Sporadic, 4, write_test_app).unwrap();
-	wait_and_reset_semaphore(TEST_SEMAPHORE_ID, 10);
+	wait_and_reset_semaphore(TEST_SEMAPHORE_ID, 2);
 	println!("IPC test Complete");
+	println!("FIFO permission test ...");
+	os_create(0, SchedulingLevel::Sporadic, 12, test_app_fifo_owner).unwrap();
+	wait_and_reset_semaphore(TEST_SEMAPHORE_ID, 2);
+	println!("FIFO permission test complete");
+	println!("Message queue test ...");
+	os_create(0, SchedulingLevel::Sporadic, 13, test_app_channel_sender).unwrap();
+	wait_and_reset_semaphore(TEST_SEMAPHORE_ID, 2);
+	println!("Message queue test complete");
+	println!("Receiver owned message queue test ...");
+	os_create(0, SchedulingLevel::Sporadic, 14, test_app_channel_owning_receiver).unwrap();
+	wait_and_reset_semaphore(TEST_SEMAPHORE_ID, 2);
+	println!("Receiver owned message queue test complete");
+	println!("Sleep test ...");
+	os_create(5, SchedulingLevel::Sporadic, 5, test_app_sleep).unwrap();
+	os_create(20, SchedulingLevel::Sporadic, 6, test_app_sleep).unwrap();
+	wait_and_reset_semaphore(TEST_SEMAPHORE_ID, 1);
+	println!("Sleep test complete");
+	println!("Device budget test ...");
+	// With its budget from the start, it runs ahead of us and would overrun the default one before we could set it
+	let budget = DeviceBudget { ticks: 2, policy: DeviceOverrunPolicy::Demote };
+	os_create_device(5, 5, budget, test_app_device_hog).unwrap();
+	wait_and_reset_semaphore(TEST_SEMAPHORE_ID, FAIRNESS_PROCESSES as i32);
+	println!("Device budget test complete");
+	println!("Fairness test ...");
+	start_fairness_test();
+	wait_and_reset_semaphore(TEST_SEMAPHORE_ID, 2);
+	check_fairness();
+	println!("Fairness test complete");
+	println!("Deadline test ...");
+	let params = DeadlineParams { period: 10, relative_deadline: 5, budget: 2 };
+	os_create_deadline(4, 1, params, test_app_deadline).unwrap();
+	os_create_deadline(5, 2, params, test_app_deadline).unwrap();
+	wait_and_reset_semaphore(TEST_SEMAPHORE_ID, 2);
+	println!("Deadline test complete");
+	println!("Float test ...");
+	for param in 1..=2 {
+		// Quantum of 1 so they get switched between as much as possible
+		let pid = os_create_sporadic(param, 0, SPORADIC_PRIORITY_LEVELS - 1, test_app_float).unwrap();
+		os_set_sporadic_quantum(pid, Some(1)).unwrap();
+	}
+	wait_and_reset_semaphore(TEST_SEMAPHORE_ID, 1);
+	println!("Float test complete");
+	println!("Thread test ...");
+	os_create(0, SchedulingLevel::Sporadic, 0, test_app_threads).unwrap();
+	wait_and_reset_semaphore(TEST_SEMAPHORE_ID, 1);
+	println!("Thread test complete");
+	println!("Syscall benchmark ...");
+	os_create_sporadic(SYSCALL_BENCH_PARAM, 0, 0, test_app_syscall_bench).unwrap();
+	wait_and_reset_semaphore(TEST_SEMAPHORE_ID, 10);
+	println!("Syscall benchmark complete");
 	println!("Scheduling test ...");
 	os_create(fifo_key as i32, SchedulingLevel::Periodic, 4, test_app).unwrap();
 	os_create(fifo_key as i32, SchedulingLevel::Periodic, 3, test_app).unwrap();
@@ -41,11 +93,32 @@ pub extern "C" fn run_tests() {
 	os_create(100000, SchedulingLevel::Sporadic, 399, crate::tests::applications::big_memory).unwrap();
 	os_wait(TEST_SEMAPHORE_ID);
 	println!("All tests complete, time to kill everything");
-	syscall1(SyscallCommand::TerminateEverythingElse);
+	syscall(SyscallCommand::TerminateEverythingElse, &[]).expect("Failed to end the other processes");
 	println!("Ah finally some quiet, try typing some stuff");
 	
 }
 
+const FAIRNESS_QUANTUM: usize = 2;
+
+/// Runs CPU bound sporadic processes at the same level, all with the same quantum
+fn start_fairness_test() {
+	let end_tick = os_get_ticks() + 60;
+	for _ in 0..FAIRNESS_PROCESSES {
+		let pid = os_create_sporadic(end_tick as i32, 0, SPORADIC_PRIORITY_LEVELS - 1, test_app_fairness).unwrap();
+		os_set_sporadic_quantum(pid, Some(FAIRNESS_QUANTUM)).unwrap();
+	}
+}
+
+/// Check that the fairness test processes all got roughly the same number of ticks
+fn check_fairness() {
+	let ticks = FAIRNESS_TICKS.iter().map(|c| c.load(Ordering::Relaxed)).collect::<Vec<usize>>();
+	println!("Ticks per process: {:?}", ticks);
+	let most = *ticks.iter().max().unwrap();
+	let least = *ticks.iter().min().unwrap();
+	// Everyone should be within a quantum of each other, give or take the tick they were preempted on
+	assert!(most - least <= FAIRNESS_QUANTUM + 1, "Sporadic processes were not scheduled fairly: {:?}", ticks);
+}
+
 fn required_count(i: i32) -> i32 {
 	assert!(i > 0);
 	(i * -1) + 1
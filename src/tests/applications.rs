@@ -1,8 +1,35 @@
 use crate::kernel::*;
 use alloc::prelude::v1::{Vec, ToOwned, String, ToString};
-use crate::processes::SchedulingLevel;
+use crate::processes::{SchedulingLevel, Name};
 use super::app_test_runner::TEST_SEMAPHORE_ID;
 use crate::println;
+use core::sync::atomic::{AtomicUsize, AtomicU32, Ordering};
+use crate::ipc::{FifoAccess, FifoError};
+use crate::ipc::channel::{self, Sender, Receiver, ChannelError};
+use crate::sync::SemaphoreId;
+use crate::interrupts::SYSCALL_FAST_RETURN;
+use crate::interrupts::syscall::SyscallError;
+
+pub const FAIRNESS_PROCESSES: usize = 3;
+/// Number of ticks each fairness test process saw itself running for
+pub static FAIRNESS_TICKS: [AtomicUsize; FAIRNESS_PROCESSES] =
+	[AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0)];
+static FAIRNESS_NEXT_SLOT: AtomicUsize = AtomicUsize::new(0);
+
+const THREAD_TEST_THREADS: usize = 3;
+const THREAD_TEST_ADDS: usize = 1000;
+/// Shared by every thread of the thread test
+static THREAD_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+const SYSCALL_BENCH_ROUNDS: u64 = 10_000;
+/// Negative, so it also checks that the result isn't mistaken for an error on the way back
+pub const SYSCALL_BENCH_PARAM: i32 = -7;
+
+// Semaphores the FIFO permission test takes turns with
+const FIFO_OWNER_TURN: SemaphoreId = 125;
+const FIFO_OUTSIDER_TURN: SemaphoreId = 126;
+/// Handed to the outsider this way, since it has to be created before the FIFO
+static PERMISSION_TEST_FIFO: AtomicU32 = AtomicU32::new(0);
 
 pub extern "C" fn test_app() {
 	use alloc::format;
@@ -40,6 +67,44 @@ pub extern "C" fn test_app_printer() {
 	os_signal(TEST_SEMAPHORE_ID);
 }
 
+/// Owns a FIFO that a process created before it can't use, until it's granted access.
+/// Closes it once that process can read it, it only goes away once the other process closes it too.
+pub extern "C" fn test_app_fifo_owner() {
+	os_init_sem(FIFO_OWNER_TURN, 0).unwrap();
+	os_init_sem(FIFO_OUTSIDER_TURN, 0).unwrap();
+	let outsider = os_create(0, SchedulingLevel::Sporadic, 11, test_app_fifo_outsider).unwrap();
+	let key = os_init_fifo(16).unwrap();
+	os_write(key, b"secret").unwrap();
+	PERMISSION_TEST_FIFO.store(key, Ordering::Relaxed);
+	os_signal(FIFO_OUTSIDER_TURN);
+	
+	os_wait(FIFO_OWNER_TURN).unwrap();
+	assert_eq!(os_grant_fifo(key, 9999, FifoAccess::Read), Err(FifoError::NoSuchProcess));
+	os_grant_fifo(key, outsider, FifoAccess::Read).unwrap();
+	os_close_fifo(key).unwrap();
+	assert_eq!(os_write(key, b"more"), Err(FifoError::PermissionDenied), "Still writing after closing");
+	os_signal(FIFO_OUTSIDER_TURN);
+	os_signal(TEST_SEMAPHORE_ID);
+}
+
+pub extern "C" fn test_app_fifo_outsider() {
+	os_wait(FIFO_OUTSIDER_TURN).unwrap();
+	let key = PERMISSION_TEST_FIFO.load(Ordering::Relaxed);
+	let mut buf = [0u8; 16];
+	assert_eq!(os_try_read(key, &mut buf), Err(FifoError::PermissionDenied), "Read a FIFO without access");
+	os_signal(FIFO_OWNER_TURN);
+	
+	os_wait(FIFO_OUTSIDER_TURN).unwrap();
+	assert_eq!(os_read(key, &mut buf), Ok(6));
+	assert_eq!(&buf[..6], b"secret");
+	assert_eq!(os_read(key, &mut buf), Ok(0), "The owner closed it, so it should have ended");
+	assert_eq!(os_write(key, b"no"), Err(FifoError::PermissionDenied), "Only granted reading");
+	os_close_fifo(key).unwrap();
+	assert_eq!(os_try_read(key, &mut buf), Err(FifoError::NoSuchFifo), "Nobody uses it, it should be gone");
+	println!("FIFO permissions work");
+	os_signal(TEST_SEMAPHORE_ID);
+}
+
 pub extern "C" fn test_app_signals() {
 	println!("Before Init");
 	os_init_sem(123, 1).unwrap();
@@ -69,23 +134,35 @@ pub extern "C" fn test_app_signals_recv() {
 	os_signal(TEST_SEMAPHORE_ID);
 }
 
+/// Writes more than the FIFO holds, so it has to wait for the reader to make space
 pub extern "C" fn write_test_app() {
 	let stuff = "Yep this works".to_owned();
 	
-	let fifo_stuff = os_init_fifo();
+	let fifo_stuff = os_init_fifo(16).unwrap();
 	os_create(fifo_stuff as i32, SchedulingLevel::Sporadic, 10, read_test_app).unwrap();
 	
 	os_write(fifo_stuff, &postcard::to_allocvec(&stuff).unwrap()).unwrap();
 	os_write(fifo_stuff, &postcard::to_allocvec(&"STUFF".to_owned()).unwrap()).unwrap();
 	os_write(fifo_stuff, &postcard::to_allocvec(&stuff).unwrap()).unwrap();
+	os_close_write(fifo_stuff).unwrap();
 	println!("Write!! {}", os_getparam());
 	os_signal(TEST_SEMAPHORE_ID);
 }
 
+/// Reads until the end, no matter how the writes were split up
 pub extern "C" fn read_test_app() {
 	let fifo_stuff = os_getparam() as u32;
-	let mut stuff_output = [0u8; 40];
-	let length_read = os_read(fifo_stuff, &mut stuff_output).unwrap();
+	// Inherited write access, it would never see the end while it could still write
+	os_close_write(fifo_stuff).unwrap();
+	let mut stuff_output = Vec::new();
+	let mut buf = [0u8; 8];
+	loop {
+		match os_read(fifo_stuff, &mut buf).unwrap() {
+			0 => break,
+			length_read => stuff_output.extend_from_slice(&buf[..length_read]),
+		}
+	}
+	assert_eq!(os_try_read(fifo_stuff, &mut buf), Ok(0), "Read past the end");
 	let (out, remaining): (String, _) =
 		postcard::take_from_bytes(&stuff_output).expect("Failed to deserialize msg");
 	println!("Read!! {:?}", out);
@@ -95,10 +172,189 @@ pub extern "C" fn read_test_app() {
 	let (out, _remaining): (String, _) =
 		postcard::take_from_bytes(remaining).expect("Failed to deserialize msg");
 	println!("Read!! {:?}", out);
-	println!("Read!! {}", length_read);
+	println!("Read!! {}", stuff_output.len());
+	os_signal(TEST_SEMAPHORE_ID);
+}
+
+/// Sends more messages than the queue holds, so it has to wait for the receiver
+pub extern "C" fn test_app_channel_sender() {
+	let (sender, _) = channel::channel::<String>(2, 64).unwrap();
+	os_create(sender.key() as i32, SchedulingLevel::Sporadic, 10, test_app_channel_receiver).unwrap();
+	
+	let too_long = "x".repeat(64);
+	match sender.try_send(&too_long) {
+		Err(ChannelError::Queue(FifoError::MessageTooLong)) => {}
+		other => panic!("Sent a message longer than the queue takes: {:?}", other),
+	}
+	send_test_messages(&sender);
+	sender.close().unwrap();
+	os_signal(TEST_SEMAPHORE_ID);
+}
+
+pub extern "C" fn test_app_channel_receiver() {
+	let mut receiver = Receiver::<String>::new(os_getparam() as u32);
+	receiver.close_sending().unwrap(); // Inherited, like the read end
+	receive_test_messages(&mut receiver);
+	os_signal(TEST_SEMAPHORE_ID);
+}
+
+/// Creates the queue but only receives, so it closes its own sending end before the sender has sent anything
+pub extern "C" fn test_app_channel_owning_receiver() {
+	let (sender, mut receiver) = channel::channel::<String>(2, 64).unwrap();
+	os_create(sender.key() as i32, SchedulingLevel::Sporadic, 10, test_app_channel_late_sender).unwrap();
+	sender.close().unwrap();
+	receive_test_messages(&mut receiver);
+	os_signal(TEST_SEMAPHORE_ID);
+}
+
+/// Can send from the start, so the receiver doesn't see the end while it waits a bit. Never closes, it just ends.
+pub extern "C" fn test_app_channel_late_sender() {
+	let sender = Sender::<String>::new(os_getparam() as u32);
+	os_sleep(2);
+	send_test_messages(&sender);
+	os_signal(TEST_SEMAPHORE_ID);
+}
+
+fn send_test_messages(sender: &Sender<String>) {
+	for message in &["first", "second", "third"] {
+		sender.send(&message.to_string()).unwrap();
+	}
+}
+
+/// Each receive gets exactly one of the messages, never parts or several at once
+fn receive_test_messages(receiver: &mut Receiver<String>) {
+	let mut received = Vec::new();
+	while let Some(message) = receiver.recv().unwrap() {
+		received.push(message);
+	}
+	assert_eq!(received, ["first", "second", "third"]);
+	println!("Received {:?}", received);
+}
+
+pub extern "C" fn test_app_sleep() {
+	let ticks = os_getparam() as usize;
+	let start = os_get_ticks();
+	os_sleep(ticks);
+	let slept = os_get_ticks() - start;
+	println!("Slept for {} ticks, wanted {}", slept, ticks);
+	assert!(slept >= ticks, "Woke up too early");
+	
+	let wake_tick = os_get_ticks() + ticks;
+	os_sleep_until(wake_tick);
+	assert!(os_get_ticks() >= wake_tick, "Woke up too early");
 	os_signal(TEST_SEMAPHORE_ID);
 }
 
+/// Never yields, just counts the ticks it was running for until the end tick passed in the param
+pub extern "C" fn test_app_fairness() {
+	let end_tick = os_getparam() as usize;
+	let slot = FAIRNESS_NEXT_SLOT.fetch_add(1, Ordering::Relaxed);
+	let mut last_tick = os_get_ticks();
+	let mut ticks_seen = 0;
+	while last_tick < end_tick {
+		let tick = os_get_ticks();
+		if tick != last_tick {
+			ticks_seen += 1;
+			last_tick = tick;
+		}
+	}
+	FAIRNESS_TICKS[slot].store(ticks_seen, Ordering::Relaxed);
+	os_signal(TEST_SEMAPHORE_ID);
+}
+
+/// Deadline process that finishes each job well within its budget, for as many jobs as the param.
+/// Every job should make its deadline.
+pub extern "C" fn test_app_deadline() {
+	let jobs = os_getparam();
+	for _ in 0..jobs {
+		let mut a = volatile::Volatile::new(0i32);
+		for i in 0..10000 {
+			a.write(a.read().wrapping_add(i));
+		}
+		os_yield(); // Job done, wait for the next release
+	}
+	let info = os_list_processes().into_iter()
+		.find(|c| c.level == SchedulingLevel::Deadline && c.arg == jobs)
+		.expect("Can't find own process info");
+	println!("Deadline process finished {} jobs with {} misses", jobs, info.deadline_misses);
+	assert_eq!(info.deadline_misses, 0, "Light deadline process missed deadlines");
+	os_signal(TEST_SEMAPHORE_ID);
+}
+
+/// Keeps a different value (from the param) in xmm0 while being preempted by another copy of itself,
+/// checking it's never overwritten and that SSE math on it still gives the right answer
+pub extern "C" fn test_app_float() {
+	let value = os_getparam() as f64 * 1.5;
+	let doubled = (value * 2.0).to_bits();
+	let end_tick = os_get_ticks() + 20;
+	unsafe { llvm_asm!("movq $0, %xmm0" : : "r"(value.to_bits()) : "xmm0" : "volatile"); }
+	while os_get_ticks() < end_tick {
+		let kept: u64;
+		let sum: u64;
+		unsafe {
+			llvm_asm!("
+				movq %xmm0, $0
+				movapd %xmm0, %xmm1
+				addsd %xmm0, %xmm1
+				movq %xmm1, $1
+				" : "=r"(kept), "=r"(sum) : : "xmm1" : "volatile");
+		}
+		assert_eq!(kept, value.to_bits(), "xmm0 was overwritten by another process");
+		assert_eq!(sum, doubled, "SSE addition went wrong");
+	}
+	println!("Float process {} kept its registers", value);
+	os_signal(TEST_SEMAPHORE_ID);
+}
+
+/// Spawns threads that all add to the same counter, everything they added has to be there once they are joined
+pub extern "C" fn test_app_threads() {
+	let tids = (0..THREAD_TEST_THREADS)
+		.map(|i| os_thread_spawn(test_thread_adder, i as i32).expect("Failed to spawn thread"))
+		.collect::<Vec<_>>();
+	for &tid in &tids {
+		os_thread_join(tid).expect("Failed to join thread");
+	}
+	assert_eq!(THREAD_COUNTER.load(Ordering::Relaxed), THREAD_TEST_THREADS * THREAD_TEST_ADDS,
+			   "Joined before the threads were done");
+	assert!(os_thread_join(tids[0]).is_err(), "Joined a thread that already ended");
+	println!("Joined {} threads", tids.len());
+	os_signal(TEST_SEMAPHORE_ID);
+}
+
+/// Thread of `test_app_threads`, yields every now and then so the threads interleave
+pub extern "C" fn test_thread_adder() {
+	for i in 0..THREAD_TEST_ADDS {
+		THREAD_COUNTER.fetch_add(1, Ordering::Relaxed);
+		if i % 100 == 0 {
+			os_yield();
+		}
+	}
+}
+
+/// Average latency of getparam and yield, returning through iretq and then with the fast path.
+/// Runs at the highest sporadic priority on its own, so the yields come straight back without a switch.
+/// Gets `SYSCALL_BENCH_PARAM` as its param, both ways of returning have to hand it back unchanged.
+pub extern "C" fn test_app_syscall_bench() {
+	for &fast in &[false, true] {
+		SYSCALL_FAST_RETURN.store(fast, Ordering::Relaxed);
+		assert_eq!(os_getparam(), SYSCALL_BENCH_PARAM, "Wrong result with fast return {}", fast);
+		let getparam_ns = time_syscall(|| { os_getparam(); });
+		let yield_ns = time_syscall(os_yield);
+		assert_eq!(os_getparam(), SYSCALL_BENCH_PARAM, "Wrong result after yielding with fast return {}", fast);
+		println!("{}: getparam {} ns, yield {} ns", if fast { "Fast return" } else { "iretq" }, getparam_ns, yield_ns);
+	}
+	os_signal(TEST_SEMAPHORE_ID);
+}
+
+/// Average nanoseconds `call` takes
+fn time_syscall(call: fn()) -> u64 {
+	let start = os_time_ns();
+	for _ in 0..SYSCALL_BENCH_ROUNDS {
+		call();
+	}
+	(os_time_ns() - start) / SYSCALL_BENCH_ROUNDS
+}
+
 pub extern "C" fn test_app_spor() {
 	let mut a: i64 = 0;
 	let param = os_getparam();
@@ -125,12 +381,34 @@ pub extern "C" fn test_app_device() {
 		println!("Reminder for stuff from {} {} ", param, i);
 		os_yield();
 	}
-	os_create(param + 10, SchedulingLevel::Device, (param + 10) as u64, test_app_device).unwrap();
+	let created = os_create(param + 10, SchedulingLevel::Device, (param + 10) as u64, test_app_device);
+	if param == 10 || param == 15 {
+		// The runner waits for 20 and 25, there's always room for them next to the devices that created them
+		created.unwrap();
+	} else if let Err(err) = created {
+		// Only ever refused for lack of CPU time, never for being invalid
+		assert_eq!(err, SyscallError::Busy, "Device {} was refused", param + 10);
+		println!("Device {} was not admitted", param + 10);
+	}
 	if param == 20 || param == 25 {
 		os_signal(TEST_SEMAPHORE_ID);
 	}
 }
 
+/// Device that takes way longer than a tick, it should get demoted instead of starving everything else
+/// Gets the device name it was created with as its param
+pub extern "C" fn test_app_device_hog() {
+	do_work();
+	do_work();
+	// Only a device can go over its budget, so this can't be another process with the same name
+	let hog = os_list_processes().into_iter()
+		.find(|info| info.name == os_getparam() as Name && info.device_overruns > 0)
+		.expect("The hog never went over its budget");
+	assert_eq!(hog.level, SchedulingLevel::Sporadic, "The hog wasn't demoted");
+	println!("Hog finished");
+	os_signal(TEST_SEMAPHORE_ID);
+}
+
 pub extern "C" fn big_memory() {
 	let param = os_getparam();
 	let mut array_of_arrays = alloc::vec::Vec::with_capacity(16);
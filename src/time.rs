@@ -0,0 +1,125 @@
+use crate::interrupts::pit::{self, PIT_BASE_FREQUENCY_HZ};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use core::arch::x86_64::{_rdtsc, __cpuid};
+use crate::eprintln;
+
+/// Frequency of the timer interrupts (the local APIC timer, or the PIT without an APIC), every one of them is a scheduler tick.
+/// All the scheduling in ticks (periodic slots, device periods, sleeps) scales with this.
+pub const TICK_FREQUENCY_HZ: u64 = 100;
+
+pub const PIT_DIVISOR: u16 = pit::divisor_for(TICK_FREQUENCY_HZ);
+
+/// Number of PIT counts in a tick, the divisor of 0 counts as 65536
+const COUNTS_PER_TICK: u64 = if PIT_DIVISOR == 0 { 0x10000 } else { PIT_DIVISOR as u64 };
+
+/// Real length of a tick, which isn't exactly 1 / `TICK_FREQUENCY_HZ` since the divisor has to be a whole number.
+/// The local APIC timer is calibrated to the same length, so ticks are the same with either timer.
+pub const TICK_LENGTH_NS: u64 = COUNTS_PER_TICK * 1_000_000_000 / PIT_BASE_FREQUENCY_HZ;
+
+/// Scheduler ticks so far, counted by the boot CPU on every timer interrupt before it takes the process manager,
+/// so none are lost while another CPU is in a syscall
+static TICKS: AtomicUsize = AtomicUsize::new(0);
+
+/// Called by the timer interrupt on the boot CPU only
+pub fn count_tick() {
+	TICKS.fetch_add(1, Ordering::SeqCst);
+}
+
+pub fn ticks() -> usize {
+	TICKS.load(Ordering::SeqCst)
+}
+
+/// Saturates, like `us_to_ticks`
+pub fn ticks_to_us(ticks: usize) -> u64 {
+	(ticks as u64).saturating_mul(COUNTS_PER_TICK * 1_000_000) / PIT_BASE_FREQUENCY_HZ
+}
+
+/// Rounded up, so anything waiting for `us` never waits for less than that.
+/// Saturates, so waiting for a huge time means waiting (practically) forever instead of overflowing.
+pub fn us_to_ticks(us: u64) -> usize {
+	let counts_per_us = COUNTS_PER_TICK * 1_000_000;
+	(us.saturating_mul(PIT_BASE_FREQUENCY_HZ).saturating_add(counts_per_us - 1) / counts_per_us) as usize
+}
+
+pub fn ms_to_ticks(ms: u64) -> usize {
+	us_to_ticks(ms.saturating_mul(1000))
+}
+
+/// Length of the PIT measurement the TSC is calibrated against, 50ms fits in the 16 bit counter
+const CALIBRATION_PIT_COUNTS: u16 = (PIT_BASE_FREQUENCY_HZ / 20) as u16;
+
+/// 0 until `tsc_init` has run
+static TSC_FREQUENCY_HZ: AtomicU64 = AtomicU64::new(0);
+static TSC_AT_BOOT: AtomicU64 = AtomicU64::new(0);
+
+/// Work out the TSC frequency against the PIT, and use the current TSC value as time 0.
+/// Has to run before interrupts are enabled.
+pub fn tsc_init() {
+	if !has_invariant_tsc() {
+		// Without it the TSC might change speed or stop in sleep states (e.g. in a lot of VMs it's just not advertised)
+		eprintln!("WARNING: TSC is not invariant, os_time_ns might drift");
+	}
+	let cycles = pit::measure_tsc_cycles(CALIBRATION_PIT_COUNTS);
+	let frequency_hz = cycles * PIT_BASE_FREQUENCY_HZ / CALIBRATION_PIT_COUNTS as u64;
+	TSC_FREQUENCY_HZ.store(frequency_hz, Ordering::Relaxed);
+	TSC_AT_BOOT.store(unsafe { _rdtsc() }, Ordering::Relaxed);
+}
+
+fn has_invariant_tsc() -> bool {
+	let max_extended_leaf = unsafe { __cpuid(0x8000_0000) }.eax;
+	max_extended_leaf >= 0x8000_0007 && unsafe { __cpuid(0x8000_0007) }.edx & (1 << 8) != 0
+}
+
+pub fn tsc_frequency_hz() -> u64 {
+	TSC_FREQUENCY_HZ.load(Ordering::Relaxed)
+}
+
+/// Nanoseconds since `tsc_init`. Never goes backwards on one CPU, but the TSCs of different CPUs aren't synchronized,
+/// so times read on different CPUs can be a little off from each other. A TSC that is behind the boot CPU's gives 0
+/// until it catches up, instead of wrapping around.
+pub fn time_ns() -> u64 {
+	let frequency_hz = tsc_frequency_hz();
+	assert_ne!(frequency_hz, 0, "TSC used before it was calibrated");
+	let cycles = unsafe { _rdtsc() }.saturating_sub(TSC_AT_BOOT.load(Ordering::Relaxed));
+	// u128 so the multiplication doesn't overflow after a few seconds
+	(cycles as u128 * 1_000_000_000 / frequency_hz as u128) as u64
+}
+
+#[cfg(test)]
+mod test {
+	use super::{ticks_to_us, us_to_ticks, TICK_LENGTH_NS, COUNTS_PER_TICK, PIT_BASE_FREQUENCY_HZ, time_ns,
+				tsc_frequency_hz};
+	use crate::{serial_print, serial_println};
+	
+	#[test_case]
+	fn test_tick_conversion() {
+		serial_print!("test_tick_conversion... ");
+		
+		assert_eq!(us_to_ticks(0), 0);
+		assert_eq!(us_to_ticks(1), 1);
+		assert_eq!(ticks_to_us(1), TICK_LENGTH_NS / 1000);
+		for &ticks in &[1, 7, 100, 12345] {
+			assert_eq!(us_to_ticks(ticks_to_us(ticks)), ticks);
+		}
+		// Saturated instead of overflowing
+		assert_eq!(us_to_ticks(u64::MAX), (u64::MAX / (COUNTS_PER_TICK * 1_000_000)) as usize);
+		assert_eq!(ticks_to_us(usize::MAX), u64::MAX / PIT_BASE_FREQUENCY_HZ);
+		
+		serial_println!("[ok]");
+	}
+	
+	#[test_case]
+	fn test_time_ns_monotonic() {
+		serial_print!("test_time_ns_monotonic... ");
+		
+		assert_ne!(tsc_frequency_hz(), 0);
+		let mut last = time_ns();
+		for _ in 0..1000 {
+			let now = time_ns();
+			assert!(now >= last, "Time went backwards");
+			last = now;
+		}
+		
+		serial_println!("[ok]");
+	}
+}
\ No newline at end of file
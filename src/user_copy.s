@@ -0,0 +1,18 @@
+.intel_syntax noprefix
+
+.global asm_copy_user
+.global asm_copy_user_instruction
+.global asm_copy_user_fixup
+
+//; asm_copy_user(dest: *mut u8, src: *const u8, len: usize) -> usize
+//; Returns 0, or the number of bytes that were left when it faulted. The page fault handler
+//; recognizes a fault on asm_copy_user_instruction, and carries on at asm_copy_user_fixup instead of panicking.
+asm_copy_user:
+	mov rcx, rdx // rdi and rsi are already dest and src
+asm_copy_user_instruction:
+	rep movsb
+	xor rax, rax
+	ret
+asm_copy_user_fixup:
+	mov rax, rcx // rep movsb counts rcx down as it goes
+	ret
\ No newline at end of file